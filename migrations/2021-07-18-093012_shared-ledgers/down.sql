@@ -0,0 +1,3 @@
+alter table history drop column ledger_id;
+alter table users drop column ledger_id;
+DROP TABLE IF EXISTS ledgers;
@@ -0,0 +1,11 @@
+CREATE TABLE ledgers
+(
+    id          varchar(20) PRIMARY KEY NOT NULL REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    invite_code varchar(16) UNIQUE      NOT NULL
+);
+
+alter table users add column ledger_id varchar(20) REFERENCES ledgers (id) ON DELETE SET NULL;
+
+alter table history add column ledger_id varchar(20);
+update history set ledger_id = user_id;
+alter table history alter column ledger_id set NOT NULL;
@@ -0,0 +1,6 @@
+delete from dialogs where chat_id <> user_id;
+
+alter table dialogs drop constraint dialogs_pkey;
+alter table dialogs add PRIMARY KEY (user_id);
+
+alter table dialogs drop column chat_id;
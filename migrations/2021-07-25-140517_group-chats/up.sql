@@ -0,0 +1,6 @@
+alter table dialogs add column chat_id varchar(20);
+update dialogs set chat_id = user_id;
+alter table dialogs alter column chat_id set NOT NULL;
+
+alter table dialogs drop constraint dialogs_pkey;
+alter table dialogs add PRIMARY KEY (chat_id, user_id);
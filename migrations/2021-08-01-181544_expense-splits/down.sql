@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS splits;
+alter table users drop column username;
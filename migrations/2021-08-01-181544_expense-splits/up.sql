@@ -0,0 +1,9 @@
+alter table users add column username varchar(32);
+
+CREATE TABLE splits
+(
+    history_id integer     NOT NULL REFERENCES history (id) ON DELETE CASCADE,
+    user_id    varchar(20) NOT NULL REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    amount     float4      NOT NULL,
+    PRIMARY KEY (history_id, user_id)
+);
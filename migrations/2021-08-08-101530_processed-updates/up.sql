@@ -0,0 +1,5 @@
+CREATE TABLE processed_updates
+(
+    update_id bigint    PRIMARY KEY NOT NULL,
+    processed timestamp NOT NULL DEFAULT now()
+);
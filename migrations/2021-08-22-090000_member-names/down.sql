@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS history_ledger_id_idx;
+alter table users drop column first_name;
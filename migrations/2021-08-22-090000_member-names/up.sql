@@ -0,0 +1,3 @@
+alter table users add column first_name varchar(64);
+
+CREATE INDEX history_ledger_id_idx ON history (ledger_id);
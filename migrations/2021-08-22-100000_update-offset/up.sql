@@ -0,0 +1,5 @@
+CREATE TABLE update_offset
+(
+    id             integer PRIMARY KEY NOT NULL,
+    next_update_id bigint  NOT NULL
+);
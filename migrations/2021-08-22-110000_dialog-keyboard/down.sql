@@ -0,0 +1 @@
+alter table dialogs drop column keyboard_message_id;
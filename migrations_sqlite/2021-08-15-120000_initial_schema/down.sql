@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS processed_updates;
+DROP TABLE IF EXISTS splits;
+DROP TABLE IF EXISTS history;
+DROP TABLE IF EXISTS dialogs;
+DROP TABLE IF EXISTS users;
+DROP TABLE IF EXISTS ledgers;
@@ -0,0 +1,48 @@
+-- Schema of the Postgres migrations in a single step, SQLite can not alter constraints of tables.
+CREATE TABLE ledgers
+(
+    id          varchar(20) PRIMARY KEY NOT NULL REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    invite_code varchar(16) UNIQUE      NOT NULL
+);
+
+CREATE TABLE users
+(
+    id        varchar(20) PRIMARY KEY NOT NULL,
+    currency  varchar(32),
+    ledger_id varchar(20) REFERENCES ledgers (id) ON DELETE SET NULL,
+    username  varchar(32)
+);
+
+CREATE TABLE dialogs
+(
+    user_id varchar(20) NOT NULL REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    command varchar(32) NOT NULL,
+    step    varchar(32),
+    chat_id varchar(20) NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+CREATE TABLE history
+(
+    id        integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id   varchar(20) NOT NULL REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    amount    real        NOT NULL,
+    category  varchar(32),
+    created   timestamp   NOT NULL,
+    updated   timestamp,
+    ledger_id varchar(20) NOT NULL
+);
+
+CREATE TABLE splits
+(
+    history_id integer     NOT NULL REFERENCES history (id) ON DELETE CASCADE,
+    user_id    varchar(20) NOT NULL REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    amount     real        NOT NULL,
+    PRIMARY KEY (history_id, user_id)
+);
+
+CREATE TABLE processed_updates
+(
+    update_id bigint    PRIMARY KEY NOT NULL,
+    processed timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
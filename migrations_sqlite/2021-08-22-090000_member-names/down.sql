@@ -0,0 +1,2 @@
+-- SQLite of the bundled version can not drop columns, the name is left in place.
+DROP INDEX IF EXISTS history_ledger_id_idx;
@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN first_name varchar(64);
+
+CREATE INDEX history_ledger_id_idx ON history (ledger_id);
@@ -0,0 +1,2 @@
+-- SQLite of the bundled version can not drop columns, the column is left in place.
+SELECT 1;
@@ -0,0 +1 @@
+alter table dialogs add column keyboard_message_id bigint;
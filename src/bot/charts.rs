@@ -0,0 +1,307 @@
+use std::cmp::Ordering;
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::fmt::Display;
+
+use chrono::{Duration, NaiveDate};
+use plotters::prelude::*;
+use plotters_bitmap::BitMapBackend;
+
+use crate::bot::error::BotError;
+use crate::db::models::history::HistoryEntity;
+
+/// Width and height of rendered charts in pixels.
+const CHART_SIZE: (u32, u32) = (640, 480);
+
+const MARGIN: i32 = 20;
+
+/// Number of days shown on the timeline chart.
+const TIMELINE_DAYS: usize = 30;
+
+/// Categories beyond this number are merged into one.
+const MAX_CATEGORIES: usize = 8;
+
+const OTHER_CATEGORY: &str = "other";
+
+/// Colors of the chart along with emoji of the same color, the emoji serve as a legend in
+/// the caption, so no fonts are needed to render the chart.
+const PALETTE: [(RGBColor, &str); MAX_CATEGORIES] = [
+    (RGBColor(221, 46, 68), "🟥"),
+    (RGBColor(85, 172, 238), "🟦"),
+    (RGBColor(120, 177, 89), "🟩"),
+    (RGBColor(253, 203, 88), "🟨"),
+    (RGBColor(170, 142, 214), "🟪"),
+    (RGBColor(244, 144, 12), "🟧"),
+    (RGBColor(193, 105, 79), "🟫"),
+    (RGBColor(49, 55, 61), "⬛"),
+];
+
+/// PNG image of the chart with a caption explaining it.
+#[derive(Debug, Clone)]
+pub struct Chart {
+    pub image: Vec<u8>,
+    pub caption: String,
+}
+
+/// pie chart of expenses by category
+pub fn categories_chart(records: &[HistoryEntity]) -> Result<Chart, BotError> {
+    let totals = category_totals(records);
+    let total = totals.iter().map(|(_, amount)| amount).sum::<f32>();
+
+    let caption = totals
+        .iter()
+        .zip(PALETTE.iter())
+        .map(|((category, amount), (_, legend))| {
+            format!(
+                "{} {}: {} ({:.0}%)\n",
+                legend,
+                category,
+                amount,
+                amount / total * 100.0
+            )
+        })
+        .collect::<String>();
+    let shares = totals
+        .iter()
+        .map(|(_, amount)| *amount)
+        .collect::<Vec<f32>>();
+
+    Ok(Chart {
+        image: render_pie(&shares)?,
+        caption,
+    })
+}
+
+/// bar chart of expenses per day for the last days
+pub fn timeline_chart(records: &[HistoryEntity], today: NaiveDate) -> Result<Chart, BotError> {
+    let totals = daily_totals(records, today, TIMELINE_DAYS);
+    let first_day = today - Duration::days(TIMELINE_DAYS as i64 - 1);
+    let highest = totals.iter().cloned().fold(0.0, f32::max);
+
+    let caption = format!(
+        "Spent per day from {} to {}. Total {}, highest {}.",
+        first_day.format("%d.%m.%Y"),
+        today.format("%d.%m.%Y"),
+        totals.iter().sum::<f32>(),
+        highest
+    );
+
+    Ok(Chart {
+        image: render_bars(&totals)?,
+        caption,
+    })
+}
+
+/// totals by category sorted from the largest, the smallest categories are merged into one
+fn category_totals(records: &[HistoryEntity]) -> Vec<(String, f32)> {
+    let mut totals: Vec<(String, f32)> = vec![];
+    for record in records {
+        let category = record.category.as_deref().unwrap_or(OTHER_CATEGORY);
+        match totals.iter_mut().find(|(known, _)| known == category) {
+            Some((_, total)) => *total += record.amount,
+            None => totals.push((category.to_string(), record.amount)),
+        }
+    }
+    totals.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+    if totals.len() > MAX_CATEGORIES {
+        // uncategorized records already make the "other" bucket, the smallest are added to it
+        let other = match totals.iter().position(|(known, _)| known == OTHER_CATEGORY) {
+            Some(index) => totals.remove(index).1,
+            None => 0.0,
+        };
+        let rest = other
+            + totals[MAX_CATEGORIES - 1..]
+                .iter()
+                .map(|(_, amount)| amount)
+                .sum::<f32>();
+        totals.truncate(MAX_CATEGORIES - 1);
+        totals.push((OTHER_CATEGORY.to_string(), rest));
+    }
+    totals
+}
+
+/// totals of each of the days ending with today
+fn daily_totals(records: &[HistoryEntity], today: NaiveDate, days: usize) -> Vec<f32> {
+    let first_day = today - Duration::days(days as i64 - 1);
+    let mut totals = vec![0.0; days];
+    for record in records {
+        let day = record.created.date();
+        if day >= first_day && day <= today {
+            totals[(day - first_day).num_days() as usize] += record.amount;
+        }
+    }
+    totals
+}
+
+fn render_pie(shares: &[f32]) -> Result<Vec<u8>, BotError> {
+    let (width, height) = CHART_SIZE;
+    let mut buffer = vec![0; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE).map_err(chart_error)?;
+
+        let center = (width as i32 / 2, height as i32 / 2);
+        let radius = f64::from(width.min(height)) / 2.0 - f64::from(MARGIN);
+        let total = shares.iter().sum::<f32>();
+        let mut start = -FRAC_PI_2;
+        for (share, (color, _)) in shares.iter().zip(PALETTE.iter()) {
+            let sweep = f64::from(share / total) * 2.0 * PI;
+            let steps = sweep.to_degrees().ceil().max(1.0) as usize;
+            let mut points = vec![center];
+            for step in 0..=steps {
+                let angle = start + sweep * step as f64 / steps as f64;
+                points.push((
+                    center.0 + (radius * angle.cos()).round() as i32,
+                    center.1 + (radius * angle.sin()).round() as i32,
+                ));
+            }
+            root.draw(&Polygon::new(points, color.filled()))
+                .map_err(chart_error)?;
+            start += sweep;
+        }
+        root.present().map_err(chart_error)?;
+    }
+    encode_png(&buffer)
+}
+
+fn render_bars(totals: &[f32]) -> Result<Vec<u8>, BotError> {
+    let (width, height) = CHART_SIZE;
+    let mut buffer = vec![0; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE).map_err(chart_error)?;
+
+        let baseline = height as i32 - MARGIN;
+        let highest = totals.iter().cloned().fold(0.0, f32::max).max(f32::EPSILON);
+        let bar_width = (width as i32 - 2 * MARGIN) / totals.len().max(1) as i32;
+        let (color, _) = PALETTE[1];
+        for (day, total) in totals.iter().enumerate() {
+            let left = MARGIN + day as i32 * bar_width;
+            let bar_height = (total / highest * (baseline - MARGIN) as f32).round() as i32;
+            root.draw(&Rectangle::new(
+                [
+                    (left + 1, baseline - bar_height),
+                    (left + bar_width - 1, baseline),
+                ],
+                color.filled(),
+            ))
+            .map_err(chart_error)?;
+        }
+        root.draw(&Rectangle::new(
+            [(MARGIN, baseline), (width as i32 - MARGIN, baseline + 1)],
+            BLACK.filled(),
+        ))
+        .map_err(chart_error)?;
+        root.present().map_err(chart_error)?;
+    }
+    encode_png(&buffer)
+}
+
+fn encode_png(buffer: &[u8]) -> Result<Vec<u8>, BotError> {
+    let mut image = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut image, CHART_SIZE.0, CHART_SIZE.1);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(chart_error)?;
+        writer.write_image_data(buffer).map_err(chart_error)?;
+    }
+    Ok(image)
+}
+
+fn chart_error<E: Display>(err: E) -> BotError {
+    BotError::ChartError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_helpers;
+
+    const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    fn record(day: u32, amount: f32, category: Option<&str>) -> HistoryEntity {
+        test_helpers::record("user_id", day, amount, category)
+    }
+
+    #[test]
+    fn categories_are_summed_and_sorted() {
+        let records = vec![
+            record(1, 10.0, Some("taxi")),
+            record(2, 20.0, Some("food")),
+            record(3, 15.0, Some("taxi")),
+            record(3, 5.0, None),
+        ];
+
+        assert_eq!(
+            category_totals(&records),
+            vec![
+                ("taxi".to_string(), 25.0),
+                ("food".to_string(), 20.0),
+                ("other".to_string(), 5.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn smallest_categories_are_merged() {
+        let records = (1..=10)
+            .map(|day| record(day, day as f32, Some(&format!("category {}", day))))
+            .collect::<Vec<HistoryEntity>>();
+
+        let totals = category_totals(&records);
+
+        assert_eq!(totals.len(), MAX_CATEGORIES);
+        assert_eq!(totals[0], ("category 10".to_string(), 10.0));
+        assert_eq!(totals[MAX_CATEGORIES - 1], ("other".to_string(), 6.0));
+    }
+
+    #[test]
+    fn smallest_categories_are_merged_into_uncategorized() {
+        let mut records = (1..=10)
+            .map(|day| record(day, day as f32, Some(&format!("category {}", day))))
+            .collect::<Vec<HistoryEntity>>();
+        records.push(record(11, 100.0, None));
+
+        let totals = category_totals(&records);
+
+        assert_eq!(totals.len(), MAX_CATEGORIES);
+        let others = totals
+            .iter()
+            .filter(|(category, _)| category == OTHER_CATEGORY)
+            .collect::<Vec<_>>();
+        assert_eq!(others, vec![&("other".to_string(), 106.0)]);
+        assert_eq!(totals[0], ("category 10".to_string(), 10.0));
+    }
+
+    #[test]
+    fn daily_totals_skip_old_records() {
+        let records = vec![
+            record(1, 10.0, None),
+            record(9, 5.0, None),
+            record(10, 1.0, None),
+            record(10, 2.0, None),
+        ];
+
+        assert_eq!(
+            daily_totals(&records, NaiveDate::from_ymd(2021, 8, 10), 3),
+            vec![0.0, 5.0, 3.0]
+        );
+    }
+
+    #[test]
+    fn charts_are_rendered_to_png() {
+        let records = vec![record(1, 10.0, Some("taxi")), record(2, 20.0, Some("food"))];
+
+        let chart = categories_chart(&records).unwrap();
+        assert_eq!(chart.image[..8], PNG_SIGNATURE);
+        assert_eq!(chart.caption, "🟥 food: 20 (67%)\n🟦 taxi: 10 (33%)\n");
+
+        let chart = timeline_chart(&records, NaiveDate::from_ymd(2021, 8, 2)).unwrap();
+        assert_eq!(chart.image[..8], PNG_SIGNATURE);
+        assert_eq!(
+            chart.caption,
+            "Spent per day from 04.07.2021 to 02.08.2021. Total 30, highest 20."
+        );
+    }
+}
@@ -1,16 +1,21 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
-use log::{error, info};
+use diesel::result::Error as QueryError;
+use log::info;
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
 
-use crate::bot::dialogs::{Command, Dialog};
+use crate::bot::dialogs::{registered_user, Command, Dialog};
 use crate::bot::error::BotError;
+use crate::bot::split::{Expense, SPLIT_USAGE};
+use crate::db::error::DbError;
 use crate::db::models::dialog::DialogEntity;
-use crate::db::models::history::{HistoryPatch, HistoryRepository};
-use crate::db::Connection;
-use crate::telegram::client::TelegramClient;
-use crate::telegram::types::Message;
+use crate::db::models::history::{HistoryPatch, NewHistoryRecord};
+use crate::db::store::Store;
+use crate::log::redact::{Id, Text};
+use crate::telegram::client::TelegramService;
+use crate::telegram::types::{Message, ReceivedMessage};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
 pub enum Add {
@@ -41,45 +46,37 @@ impl Dialog<Add> {
 
     pub async fn handle_current_step(
         &self,
-        conn: &Connection,
-        telegram_client: &TelegramClient,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
         user_id: &str,
         payload: &str,
-    ) -> Result<String, BotError> {
-        info!("Received {} payload from user {}", payload, user_id);
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
 
         match self.current_step {
             Some(Add::Amount) => {
                 let parsed_value = match f32::from_str(payload) {
                     Ok(value) => value,
-                    Err(err) => {
-                        let _ = telegram_client
-                            .send_message(&Message {
-                                chat_id: &user_id,
-                                text: format!(
-                                    "Can not parse: {} to number. Try /add again.",
-                                    payload
-                                )
-                                .as_str(),
-                                ..Default::default()
-                            })
-                            .await?;
-                        error!("failed to create new user: {}", err);
-                        return Err(BotError::ParsingError(err));
-                    }
+                    Err(_) => return Err(BotError::ValidationError(payload.to_string())),
                 };
-                HistoryRepository::add_expense_record(user_id.to_string(), parsed_value, conn)?;
-                DialogEntity::update_dialog(
-                    &DialogEntity::new(
-                        user_id.to_string(),
-                        Command::Add.to_string(),
-                        Some(Add::Category.to_string()),
-                    ),
-                    conn,
-                )?;
+                let user = registered_user(db, user_id).await?;
+                db.add_expense_record(user_id, user.ledger_for_chat(chat_id), parsed_value)
+                    .await?;
+                db.save_dialog(DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    Command::Add.to_string(),
+                    Some(Add::Category.to_string()),
+                ))
+                .await?;
                 Ok(telegram_client
                     .send_message(&Message {
-                        chat_id: &user_id,
+                        chat_id,
                         text: format!("Write a category where you have spent {}.", parsed_value)
                             .as_str(),
                         ..Default::default()
@@ -87,35 +84,47 @@ impl Dialog<Add> {
                     .await?)
             }
             Some(Add::Category) => {
-                DialogEntity::update_dialog(
-                    &DialogEntity::new(user_id.to_string(), Command::Start.to_string(), None),
-                    conn,
-                )?;
-                HistoryRepository::update_latest_expense_record(
+                db.save_dialog(DialogEntity::new(
+                    chat_id.to_string(),
                     user_id.to_string(),
-                    &HistoryPatch::new(None, Some(payload.to_string())),
-                    conn,
-                )?;
+                    Command::Start.to_string(),
+                    None,
+                ))
+                .await?;
+                db.update_latest_expense_record(
+                    user_id,
+                    HistoryPatch::new(None, Some(payload.to_string())),
+                )
+                .await?;
                 Ok(telegram_client
                     .send_message(&Message {
-                        chat_id: &user_id,
+                        chat_id,
                         text: "Record has been saved",
                         ..Default::default()
                     })
                     .await?)
             }
+            None if !payload.is_empty() => {
+                let text = save_expense(db, chat_id, user_id, payload).await?;
+                Ok(telegram_client
+                    .send_message(&Message {
+                        chat_id,
+                        text: &text,
+                        ..Default::default()
+                    })
+                    .await?)
+            }
             None => {
-                DialogEntity::update_dialog(
-                    &DialogEntity::new(
-                        user_id.to_string(),
-                        Command::Add.to_string(),
-                        Some(Add::Amount.to_string()),
-                    ),
-                    conn,
-                )?;
+                db.save_dialog(DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    Command::Add.to_string(),
+                    Some(Add::Amount.to_string()),
+                ))
+                .await?;
                 Ok(telegram_client
                     .send_message(&Message {
-                        chat_id: &user_id,
+                        chat_id,
                         text: "Write amount of money you have spent",
                         ..Default::default()
                     })
@@ -124,3 +133,66 @@ impl Dialog<Add> {
         }
     }
 }
+
+/// saves expense sent along with the command, e.g. `/add 90 dinner split @alice @bob`,
+/// returns text of the reply
+async fn save_expense(
+    db: &dyn Store,
+    chat_id: &str,
+    user_id: &str,
+    payload: &str,
+) -> Result<String, BotError> {
+    let expense = match Expense::from_str(payload) {
+        Ok(expense) => expense,
+        Err(reason) => return Ok(format!("{} {}", reason, SPLIT_USAGE)),
+    };
+
+    let user = registered_user(db, user_id).await?;
+    let ledger_id = user.ledger_for_chat(chat_id);
+    let split_amounts = match &expense.split {
+        None => vec![],
+        Some(split) => {
+            let mut user_ids = HashMap::new();
+            for username in split.members() {
+                match db.find_ledger_member(ledger_id, username).await {
+                    Ok(member) => {
+                        let _ = user_ids.insert(username.to_string(), member.id);
+                    }
+                    Err(DbError::Query(QueryError::NotFound)) => {
+                        return Ok(format!(
+                            "@{} is not a member of this ledger yet. Members join it with /join, in groups they join by adding a record.",
+                            username
+                        ))
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            let split = split.map_members(|username| user_ids[username].clone());
+            match split.amounts(expense.amount, user_id) {
+                Ok(amounts) => amounts,
+                Err(reason) => return Ok(reason),
+            }
+        }
+    };
+
+    let split_count = split_amounts.len();
+    db.add_expense_record_with_splits(
+        NewHistoryRecord::new(
+            user_id.to_string(),
+            ledger_id.to_string(),
+            expense.amount,
+            expense.category,
+        ),
+        split_amounts,
+    )
+    .await?;
+
+    if split_count == 0 {
+        Ok("Record has been saved".to_string())
+    } else {
+        Ok(format!(
+            "Record has been saved and split between {} members",
+            split_count
+        ))
+    }
+}
@@ -0,0 +1,136 @@
+use chrono::Utc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::log::redact::{Id, Text};
+use crate::{
+    bot::{
+        charts::{categories_chart, timeline_chart},
+        dialogs::{registered_user, Command, Dialog},
+        error::BotError,
+    },
+    db::{models::dialog::DialogEntity, store::Store},
+    telegram::{
+        client::TelegramService,
+        types::{
+            Image, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, Message, ReceivedMessage,
+            ReplyMarkup,
+        },
+    },
+};
+
+const BY_CATEGORY: &str = "By category";
+const BY_DAY: &str = "By day";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
+pub enum Chart {
+    Kind,
+}
+
+impl Default for Dialog<Chart> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dialog<Chart> {
+    pub fn new() -> Self {
+        Dialog {
+            command: Command::Chart,
+            current_step: None,
+        }
+    }
+
+    pub async fn handle_current_step(
+        &self,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
+        user_id: &str,
+        payload: &str,
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
+
+        match self.current_step {
+            Some(Chart::Kind) => {
+                db.save_dialog(DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    Command::Start.to_string(),
+                    None,
+                ))
+                .await?;
+                let user = registered_user(db, user_id).await?;
+                let records = db.get_ledger_records(user.ledger_for_chat(chat_id)).await?;
+
+                let chart = match payload {
+                    BY_CATEGORY | BY_DAY if records.is_empty() => None,
+                    BY_CATEGORY => Some(categories_chart(&records)?),
+                    BY_DAY => Some(timeline_chart(&records, Utc::now().naive_utc().date())?),
+                    _ => {
+                        return Ok(telegram_client
+                            .send_message(&Message {
+                                chat_id,
+                                text: format!("Unknown chart: {}. Try /chart again.", payload)
+                                    .as_str(),
+                                ..Default::default()
+                            })
+                            .await?)
+                    }
+                };
+
+                match chart {
+                    Some(chart) => Ok(telegram_client
+                        .send_photo(&Image {
+                            chat_id,
+                            photo: InputFile::Upload {
+                                file_name: "chart.png",
+                                bytes: &chart.image,
+                            },
+                            caption: Some(&chart.caption),
+                            ..Default::default()
+                        })
+                        .await?),
+                    None => Ok(telegram_client
+                        .send_message(&Message {
+                            chat_id,
+                            text: "There are no records yet. Use /add to save one.",
+                            ..Default::default()
+                        })
+                        .await?),
+                }
+            }
+            None => {
+                db.save_dialog(DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    Command::Chart.to_string(),
+                    Some(Chart::Kind.to_string()),
+                ))
+                .await?;
+                let reply_markup = ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
+                    inline_keyboard: vec![vec![
+                        InlineKeyboardButton::new(BY_CATEGORY),
+                        InlineKeyboardButton::new(BY_DAY),
+                    ]],
+                });
+                let sent = telegram_client
+                    .send_message(&Message {
+                        chat_id,
+                        text: "Choose a chart",
+                        reply_markup: Some(&reply_markup),
+                        ..Default::default()
+                    })
+                    .await?;
+                db.set_dialog_keyboard(chat_id, user_id, sent.message_id)
+                    .await?;
+                Ok(sent)
+            }
+        }
+    }
+}
@@ -6,9 +6,10 @@ use strum_macros::EnumString;
 use crate::bot::dialogs::{Command, Dialog};
 use crate::bot::error::BotError;
 use crate::db::models::dialog::DialogEntity;
-use crate::db::Connection;
-use crate::telegram::client::TelegramClient;
-use crate::telegram::types::Message;
+use crate::db::store::Store;
+use crate::log::redact::{Id, Text};
+use crate::telegram::client::TelegramService;
+use crate::telegram::types::{Message, ReceivedMessage};
 
 const FEEDBACK_TEXT: &str = "You can write your feedback. If you want the author to get back to \
 you, leave your email. Or you can contact the author via telegram: @privalou \
@@ -38,11 +39,12 @@ impl Dialog<Feedback> {
 
     pub async fn handle_current_step(
         &self,
-        conn: &Connection,
-        telegram_client: &TelegramClient,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
         user_id: &str,
         payload: &str,
-    ) -> Result<String, BotError> {
+    ) -> Result<ReceivedMessage, BotError> {
         let step = match self.current_step {
             None => Feedback::Start,
             Some(step) => step,
@@ -50,15 +52,16 @@ impl Dialog<Feedback> {
         match step {
             Feedback::Start => {
                 let entity = DialogEntity::new(
+                    chat_id.to_string(),
                     user_id.to_string(),
                     Command::Feedback.to_string(),
                     Some(Feedback::Input.to_string()),
                 );
-                DialogEntity::update_dialog(&entity, conn)?;
+                db.save_dialog(entity).await?;
 
                 Ok(telegram_client
                     .send_message(&Message {
-                        chat_id: &user_id,
+                        chat_id,
                         text: FEEDBACK_TEXT,
                         ..Default::default()
                     })
@@ -66,13 +69,22 @@ impl Dialog<Feedback> {
             }
 
             Feedback::Input => {
-                info!("received feedback from user({}): {}", user_id, payload);
-                let entity = DialogEntity::new(user_id.to_string(), self.command.to_string(), None);
-                DialogEntity::update_dialog(&entity, conn)?;
+                info!(
+                    "received feedback from user({}): {}",
+                    Id(user_id),
+                    Text(payload)
+                );
+                let entity = DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    self.command.to_string(),
+                    None,
+                );
+                db.save_dialog(entity).await?;
 
                 Ok(telegram_client
                     .send_message(&Message {
-                        chat_id: user_id,
+                        chat_id,
                         text: &format!("Thanks, {}, for you priceless feedback!", &user_id),
                         ..Default::default()
                     })
@@ -1,14 +1,20 @@
+use std::collections::HashMap;
+
 use log::info;
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
 
+use crate::log::redact::{Id, Text};
 use crate::{
     bot::{
-        dialogs::{Command, Dialog},
+        dialogs::{registered_user, Command, Dialog},
         error::BotError,
     },
-    db::{models::history::HistoryRepository, Connection},
-    telegram::{client::TelegramClient, types::Message},
+    db::{models::history::HistoryEntity, store::Store},
+    telegram::{
+        client::TelegramService,
+        types::{Message, ReceivedMessage},
+    },
 };
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
@@ -32,31 +38,149 @@ impl Dialog<History> {
 
     pub async fn handle_current_step(
         &self,
-        conn: &Connection,
-        telegram_client: &TelegramClient,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
         user_id: &str,
         payload: &str,
-    ) -> Result<String, BotError> {
-        info!("Received {} payload from user {}", payload, user_id);
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
 
-        let records = HistoryRepository::get_all_records(user_id.to_string(), conn)?;
+        let user = registered_user(db, user_id).await?;
+        let records = db.get_ledger_records(user.ledger_for_chat(chat_id)).await?;
 
-        let stringified_records = records
-            .iter()
-            .map(|a| {
-                let string = format!("{:?}", a);
-                string
-            })
-            .collect::<String>();
+        let text = if records.is_empty() {
+            "There are no records yet. Use /add to save one.".to_string()
+        } else {
+            let mut member_ids = records
+                .iter()
+                .map(|record| record.user_id.clone())
+                .collect::<Vec<String>>();
+            member_ids.sort();
+            member_ids.dedup();
+            let names = db
+                .get_users_by_ids(member_ids)
+                .await?
+                .into_iter()
+                .map(|member| (member.id.clone(), member.display_name()))
+                .collect::<HashMap<String, String>>();
+            format_report(&records, &names)
+        };
 
-        let _ = telegram_client
+        Ok(telegram_client
             .send_message(&Message {
-                chat_id: user_id,
-                text: &stringified_records,
+                chat_id,
+                text: &text,
                 ..Default::default()
             })
-            .await?;
+            .await?)
+    }
+}
+
+/// sums amounts of the records per member in order of their first record
+fn totals_by_member(records: &[HistoryEntity]) -> Vec<(&str, f32)> {
+    let mut totals: Vec<(&str, f32)> = vec![];
+    for record in records {
+        match totals
+            .iter_mut()
+            .find(|(member, _)| *member == record.user_id)
+        {
+            Some((_, total)) => *total += record.amount,
+            None => totals.push((record.user_id.as_str(), record.amount)),
+        }
+    }
+    totals
+}
+
+/// lists the records with totals per member, members are shown by their names,
+/// ids are shown only for members who are not known
+fn format_report(records: &[HistoryEntity], names: &HashMap<String, String>) -> String {
+    let name = |id: &str| names.get(id).map(String::as_str).unwrap_or(id).to_string();
+    let mut report = records
+        .iter()
+        .map(|record| {
+            format!(
+                "{} {}: {} {}\n",
+                record.created.format("%d.%m.%Y"),
+                name(&record.user_id),
+                record.amount,
+                record.category.as_deref().unwrap_or("")
+            )
+        })
+        .collect::<String>();
+
+    let totals = totals_by_member(records);
+    if totals.len() > 1 {
+        report.push_str("\nSpent by member:\n");
+        for (member, total) in totals {
+            report.push_str(&format!("{}: {}\n", name(member), total));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_helpers::record;
+
+    #[test]
+    fn totals_are_split_per_member() {
+        let records = vec![
+            record("alice", 18, 10.0, Some("food")),
+            record("bob", 18, 5.5, Some("taxi")),
+            record("alice", 18, 2.5, Some("coffee")),
+        ];
+
+        assert_eq!(
+            totals_by_member(&records),
+            vec![("alice", 12.5), ("bob", 5.5)]
+        );
+    }
+
+    #[test]
+    fn report_of_single_member_has_no_split() {
+        let records = vec![record("alice", 18, 10.0, Some("food"))];
+
+        assert_eq!(
+            format_report(&records, &HashMap::new()),
+            "18.08.2021 alice: 10 food\n"
+        );
+    }
+
+    #[test]
+    fn report_of_shared_ledger_has_split() {
+        let records = vec![
+            record("alice", 18, 10.0, Some("food")),
+            record("bob", 18, 5.5, Some("taxi")),
+        ];
+
+        assert_eq!(
+            format_report(&records, &HashMap::new()),
+            "18.08.2021 alice: 10 food\n18.08.2021 bob: 5.5 taxi\n\nSpent by member:\nalice: 10\nbob: 5.5\n"
+        );
+    }
+
+    #[test]
+    fn report_shows_member_names() {
+        let records = vec![
+            record("1001", 18, 10.0, Some("food")),
+            record("1002", 18, 5.5, Some("taxi")),
+        ];
+        let names = vec![
+            ("1001".to_string(), "@alice".to_string()),
+            ("1002".to_string(), "Bob".to_string()),
+        ]
+        .into_iter()
+        .collect::<HashMap<String, String>>();
 
-        Ok("Stub for history!".to_string())
+        assert_eq!(
+            format_report(&records, &names),
+            "18.08.2021 @alice: 10 food\n18.08.2021 Bob: 5.5 taxi\n\nSpent by member:\n@alice: 10\nBob: 5.5\n"
+        );
     }
 }
@@ -0,0 +1,106 @@
+use diesel::result::Error as QueryError;
+use log::info;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::bot::dialogs::{Command, Dialog};
+use crate::bot::error::BotError;
+use crate::db::error::DbError;
+use crate::db::models::dialog::DialogEntity;
+use crate::db::store::Store;
+use crate::log::redact::{Id, Text};
+use crate::telegram::client::TelegramService;
+use crate::telegram::types::{Message, ReceivedMessage};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
+pub enum Join {
+    InviteCode,
+}
+
+impl Default for Dialog<Join> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dialog<Join> {
+    pub fn new() -> Self {
+        Dialog {
+            command: Command::Join,
+            current_step: None,
+        }
+    }
+
+    pub async fn handle_current_step(
+        &self,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
+        user_id: &str,
+        payload: &str,
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
+
+        match self.current_step {
+            Some(Join::InviteCode) => {
+                db.save_dialog(DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    Command::Start.to_string(),
+                    None,
+                ))
+                .await?;
+                let ledger = match db.find_ledger_by_invite_code(payload.trim()).await {
+                    Ok(ledger) => ledger,
+                    Err(DbError::Query(QueryError::NotFound)) => {
+                        return Ok(telegram_client
+                            .send_message(&Message {
+                                chat_id,
+                                text: format!(
+                                    "There is no ledger with invite code {}. Try /join again.",
+                                    payload
+                                )
+                                .as_str(),
+                                ..Default::default()
+                            })
+                            .await?);
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+                let new_ledger_id = if ledger.id == user_id {
+                    None
+                } else {
+                    Some(ledger.id.as_str())
+                };
+                let _ = db.update_ledger(new_ledger_id, user_id).await?;
+                Ok(telegram_client
+                    .send_message(&Message {
+                        chat_id,
+                        text: "You have joined the ledger. Records you add are now shared with its members.",
+                        ..Default::default()
+                    })
+                    .await?)
+            }
+            None => {
+                db.save_dialog(DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    Command::Join.to_string(),
+                    Some(Join::InviteCode.to_string()),
+                ))
+                .await?;
+                Ok(telegram_client
+                    .send_message(&Message {
+                        chat_id,
+                        text: "Write invite code of the ledger you want to join",
+                        ..Default::default()
+                    })
+                    .await?)
+            }
+        }
+    }
+}
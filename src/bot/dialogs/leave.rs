@@ -0,0 +1,61 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::log::redact::{Id, Text};
+use crate::{
+    bot::{
+        dialogs::{Command, Dialog},
+        error::BotError,
+    },
+    db::store::Store,
+    telegram::{
+        client::TelegramService,
+        types::{Message, ReceivedMessage},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
+pub enum Leave {
+    OwnLedger,
+}
+
+impl Default for Dialog<Leave> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dialog<Leave> {
+    pub fn new() -> Self {
+        Dialog {
+            command: Command::Leave,
+            current_step: None,
+        }
+    }
+
+    pub async fn handle_current_step(
+        &self,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
+        user_id: &str,
+        payload: &str,
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
+
+        let _ = db.update_ledger(None, user_id).await?;
+
+        Ok(telegram_client
+            .send_message(&Message {
+                chat_id,
+                text: "You have left the shared ledger. Records you add are private again.",
+                ..Default::default()
+            })
+            .await?)
+    }
+}
@@ -1,19 +1,35 @@
+use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
 
+use diesel::result::Error as QueryError;
 use serde::de::DeserializeOwned;
 use serde::export::Formatter;
 
+use crate::bot::error::BotError;
+use crate::db::error::DbError;
 use crate::db::models::dialog::DialogEntity;
+use crate::db::models::user::UserEntity;
+use crate::db::store::Store;
 
 pub use self::add::Add;
+pub use self::chart::Chart;
 pub use self::feedback::Feedback;
 pub use self::history::History;
+pub use self::join::Join;
+pub use self::leave::Leave;
+pub use self::settle::Settle;
+pub use self::share::Share;
 pub use self::start::Start;
 
 mod add;
+mod chart;
 mod feedback;
 mod history;
+mod join;
+mod leave;
+mod settle;
+mod share;
 mod start;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -24,6 +40,11 @@ pub enum Command {
     Feedback,
     Help,
     History,
+    Share,
+    Join,
+    Leave,
+    Settle,
+    Chart,
 }
 
 impl fmt::Display for Command {
@@ -35,6 +56,11 @@ impl fmt::Display for Command {
             Command::Help => "/help",
             Command::Add => "/add",
             Command::History => "/history",
+            Command::Share => "/share",
+            Command::Join => "/join",
+            Command::Leave => "/leave",
+            Command::Settle => "/settle",
+            Command::Chart => "/chart",
         };
         write!(f, "{}", printable)
     }
@@ -49,30 +75,15 @@ where
     pub current_step: Option<T>,
 }
 
-impl<T> From<DialogEntity> for Dialog<T>
+impl<T> TryFrom<DialogEntity> for Dialog<T>
 where
     T: std::hash::Hash + std::cmp::Eq + DeserializeOwned + std::str::FromStr,
     <T as std::str::FromStr>::Err: std::fmt::Debug,
 {
-    fn from(dialog: DialogEntity) -> Self {
-        let command = match Command::from_str(&dialog.command) {
-            Ok(command) => command,
-            Err(_) => {
-                panic!("Dialog command can not be parsed!")
-            }
-        };
+    type Error = BotError;
 
-        let current_step = match &dialog.step {
-            Some(value) => match T::from_str(value) {
-                Ok(value) => Some(value),
-                Err(_) => None,
-            },
-            None => None,
-        };
-        Dialog {
-            command,
-            current_step,
-        }
+    fn try_from(dialog: DialogEntity) -> Result<Self, Self::Error> {
+        Dialog::try_from(&dialog)
     }
 }
 
@@ -87,23 +98,28 @@ impl FromStr for Command {
             "/help" => Ok(Command::Help),
             "/add" => Ok(Command::Add),
             "/history" => Ok(Command::History),
+            "/share" => Ok(Command::Share),
+            "/join" => Ok(Command::Join),
+            "/leave" => Ok(Command::Leave),
+            "/settle" => Ok(Command::Settle),
+            "/chart" => Ok(Command::Chart),
             _ => Err(()),
         }
     }
 }
 
-impl<T> From<&DialogEntity> for Dialog<T>
+impl<T> TryFrom<&DialogEntity> for Dialog<T>
 where
     T: std::hash::Hash + std::cmp::Eq + DeserializeOwned + std::str::FromStr,
     <T as std::str::FromStr>::Err: std::fmt::Debug,
 {
-    fn from(dialog: &DialogEntity) -> Self {
-        let command = match Command::from_str(&dialog.command) {
-            Ok(command) => command,
-            Err(_) => {
-                panic!("Dialog command can not be parsed!")
-            }
-        };
+    type Error = BotError;
+
+    /// fails when the command of the dialog is unknown, unknown step starts the dialog over
+    fn try_from(dialog: &DialogEntity) -> Result<Self, Self::Error> {
+        let command = Command::from_str(&dialog.command).map_err(|_| {
+            BotError::CorruptedDialog(format!("unknown command {}", dialog.command))
+        })?;
 
         let current_step = match &dialog.step {
             Some(value) => match T::from_str(value) {
@@ -112,10 +128,19 @@ where
             },
             None => None,
         };
-        Dialog {
+        Ok(Dialog {
             command,
             current_step,
-        }
+        })
+    }
+}
+
+/// user sending the command, a user who has not sent /start yet is not registered
+async fn registered_user(db: &dyn Store, user_id: &str) -> Result<UserEntity, BotError> {
+    match db.get_user(user_id).await {
+        Ok(user) => Ok(user),
+        Err(DbError::Query(QueryError::NotFound)) => Err(BotError::NotRegistered),
+        Err(err) => Err(err.into()),
     }
 }
 
@@ -143,12 +168,13 @@ mod tests {
     #[test]
     fn invalid_current_step_is_none() {
         let entity: DialogEntity = DialogEntity::new(
+            "user_id".to_string(),
             "user_id".to_string(),
             "/start".to_string(),
             Some("foo".to_string()),
         );
 
-        let dialog_converted: Dialog<Start> = entity.into();
+        let dialog_converted = Dialog::<Start>::try_from(entity).unwrap();
 
         assert_eq!(
             dialog_converted,
@@ -161,10 +187,14 @@ mod tests {
 
     #[test]
     fn conversion_works_with_current_step_none() {
-        let entity: DialogEntity =
-            DialogEntity::new("user_id".to_string(), "/start".to_string(), None);
+        let entity: DialogEntity = DialogEntity::new(
+            "user_id".to_string(),
+            "user_id".to_string(),
+            "/start".to_string(),
+            None,
+        );
 
-        let dialog_converted: Dialog<Start> = entity.into();
+        let dialog_converted = Dialog::<Start>::try_from(entity).unwrap();
 
         assert_eq!(
             dialog_converted,
@@ -178,12 +208,13 @@ mod tests {
     #[test]
     fn conversion_works_with_current_step_some() {
         let entity: DialogEntity = DialogEntity::new(
+            "user_id".to_string(),
             "user_id".to_string(),
             "/start".to_string(),
             Some("CurrencySelection".to_string()),
         );
 
-        let dialog_converted: Dialog<Start> = entity.into();
+        let dialog_converted = Dialog::<Start>::try_from(entity).unwrap();
 
         assert_eq!(
             dialog_converted,
@@ -193,4 +224,18 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn conversion_fails_with_unknown_command() {
+        let entity: DialogEntity = DialogEntity::new(
+            "user_id".to_string(),
+            "user_id".to_string(),
+            "/dance".to_string(),
+            None,
+        );
+
+        let result = Dialog::<Start>::try_from(entity);
+
+        assert!(matches!(result, Err(BotError::CorruptedDialog(_))));
+    }
 }
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::log::redact::{Id, Text};
+use crate::{
+    bot::{
+        dialogs::{registered_user, Command, Dialog},
+        error::BotError,
+        split::{balances, settle_up},
+    },
+    db::store::Store,
+    telegram::{
+        client::TelegramService,
+        types::{Message, ReceivedMessage},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
+pub enum Settle {
+    Transfers,
+}
+
+impl Default for Dialog<Settle> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dialog<Settle> {
+    pub fn new() -> Self {
+        Dialog {
+            command: Command::Settle,
+            current_step: None,
+        }
+    }
+
+    pub async fn handle_current_step(
+        &self,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
+        user_id: &str,
+        payload: &str,
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
+
+        let user = registered_user(db, user_id).await?;
+        let debts = db.get_ledger_debts(user.ledger_for_chat(chat_id)).await?;
+        let transfers = settle_up(&balances(&debts));
+
+        let text = if transfers.is_empty() {
+            "Everybody is settled up.".to_string()
+        } else {
+            let mut member_ids = vec![];
+            for transfer in &transfers {
+                member_ids.push(transfer.from.clone());
+                member_ids.push(transfer.to.clone());
+            }
+            let names = db
+                .get_users_by_ids(member_ids)
+                .await?
+                .into_iter()
+                .map(|member| (member.id.clone(), member.display_name()))
+                .collect::<HashMap<String, String>>();
+            let name = |id: &String| names.get(id).cloned().unwrap_or_else(|| id.clone());
+
+            let mut text = transfers
+                .iter()
+                .map(|transfer| {
+                    format!(
+                        "{} pays {} {}\n",
+                        name(&transfer.from),
+                        name(&transfer.to),
+                        transfer.amount
+                    )
+                })
+                .collect::<String>();
+            text.push_str(
+                "\nRecord a payment with /add <amount> split @member=<amount> once it is made.",
+            );
+            text
+        };
+
+        Ok(telegram_client
+            .send_message(&Message {
+                chat_id,
+                text: &text,
+                ..Default::default()
+            })
+            .await?)
+    }
+}
@@ -0,0 +1,68 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::log::redact::{Id, Text};
+use crate::{
+    bot::{
+        dialogs::{registered_user, Command, Dialog},
+        error::BotError,
+    },
+    db::store::Store,
+    telegram::{
+        client::TelegramService,
+        types::{Message, ReceivedMessage},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
+pub enum Share {
+    InviteCode,
+}
+
+impl Default for Dialog<Share> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dialog<Share> {
+    pub fn new() -> Self {
+        Dialog {
+            command: Command::Share,
+            current_step: None,
+        }
+    }
+
+    pub async fn handle_current_step(
+        &self,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
+        user_id: &str,
+        payload: &str,
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
+
+        let user = registered_user(db, user_id).await?;
+        let ledger = db
+            .get_or_create_ledger(user.ledger_for_chat(chat_id))
+            .await?;
+
+        Ok(telegram_client
+            .send_message(&Message {
+                chat_id,
+                text: format!(
+                    "Invite code of your ledger is {}. Members can join it via /join command.",
+                    ledger.invite_code
+                )
+                .as_str(),
+                ..Default::default()
+            })
+            .await?)
+    }
+}
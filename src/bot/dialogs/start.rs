@@ -5,10 +5,12 @@ use strum_macros::{Display, EnumString};
 use crate::bot::dialogs::{Command, Dialog};
 use crate::bot::error::BotError;
 use crate::db::models::dialog::DialogEntity;
-use crate::db::models::user::UserEntity;
-use crate::db::Connection;
-use crate::telegram::client::TelegramClient;
-use crate::telegram::types::{InlineKeyboardButton, InlineKeyboardMarkup, Message, ReplyMarkup};
+use crate::db::store::Store;
+use crate::log::redact::{Id, Text};
+use crate::telegram::client::TelegramService;
+use crate::telegram::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, Message, ReceivedMessage, ReplyMarkup,
+};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
 pub enum Start {
@@ -39,22 +41,32 @@ impl Dialog<Start> {
 
     pub async fn handle_current_step(
         &self,
-        conn: &Connection,
-        telegram_client: &TelegramClient,
+        db: &dyn Store,
+        telegram_client: &dyn TelegramService,
+        chat_id: &str,
         user_id: &str,
         payload: &str,
-    ) -> Result<String, BotError> {
-        info!("Received {} payload from user {}", payload, user_id);
+    ) -> Result<ReceivedMessage, BotError> {
+        info!(
+            "Received {} payload from user {}",
+            Text(payload),
+            Id(user_id)
+        );
 
         let current_step = match self.current_step {
-            None => match DialogEntity::get_user_dialog(user_id, conn) {
-                Err(_) => None,
+            None => match db.get_user_dialog(chat_id, user_id).await {
+                Err(_) => {
+                    if db.is_registered(user_id).await? {
+                        Some(Start::AlreadyRegistered)
+                    } else {
+                        None
+                    }
+                }
                 Ok(entity) => match entity.step {
                     None => Some(Start::AlreadyRegistered),
-                    Some(step) => Some(
-                        step.parse()
-                            .expect("Can not parse command. Should not happen"),
-                    ),
+                    Some(step) => Some(step.parse().map_err(|_| {
+                        BotError::CorruptedDialog(format!("unknown step {} of /start", step))
+                    })?),
                 },
             },
             Some(step) => Some(step),
@@ -62,17 +74,18 @@ impl Dialog<Start> {
 
         match current_step {
             Some(Start::CurrencySelection) => {
-                info!("received payload at Currency step {}", &payload);
+                info!("received payload at Currency step {}", Text(payload));
                 let dialog_entity = DialogEntity::new(
+                    chat_id.to_string(),
                     user_id.to_string(),
                     "/start".to_string(),
                     Some(Start::AlreadyRegistered.to_string()),
                 );
-                let _ = UserEntity::update_currency(payload, user_id, conn)?;
-                DialogEntity::update_dialog(&dialog_entity, &conn)?;
+                let _ = db.update_currency(payload, user_id).await?;
+                db.save_dialog(dialog_entity).await?;
                 Ok(telegram_client
                     .send_message(&Message {
-                        chat_id: user_id,
+                        chat_id,
                         text: format!("Your currency is {}", payload).as_str(),
                         ..Default::default()
                     })
@@ -81,17 +94,27 @@ impl Dialog<Start> {
             Some(Start::AlreadyRegistered) => {
                 info!(
                     "received payload at AlreadyRegistered step from user {}, {}",
-                    &payload, &user_id
+                    Text(payload),
+                    Id(user_id)
                 );
                 Ok(telegram_client
                     .send_message(&Message {
-                        chat_id: user_id,
+                        chat_id,
                         text: "You are already registered. Use /help to see list of available commands.",
                         ..Default::default()
                     }).await?)
             }
             None => {
-                let _ = UserEntity::save_user(user_id, conn)?;
+                let _ = db.save_user(user_id).await?;
+                if chat_id != user_id {
+                    db.save_dialog(DialogEntity::new(
+                        chat_id.to_string(),
+                        user_id.to_string(),
+                        Command::Start.to_string(),
+                        Some(Start::CurrencySelection.to_string()),
+                    ))
+                    .await?;
+                }
                 let reply_markup = ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
                     inline_keyboard: vec![vec![
                         InlineKeyboardButton::new("₽"),
@@ -99,14 +122,17 @@ impl Dialog<Start> {
                         InlineKeyboardButton::new("€"),
                     ]],
                 });
-                Ok(telegram_client
+                let sent = telegram_client
                     .send_message(&Message {
-                        chat_id: user_id,
+                        chat_id,
                         text: "Choose your currency",
                         reply_markup: Some(&reply_markup),
                         ..Default::default()
                     })
-                    .await?)
+                    .await?;
+                db.set_dialog_keyboard(chat_id, user_id, sent.message_id)
+                    .await?;
+                Ok(sent)
             }
         }
     }
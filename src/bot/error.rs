@@ -1,48 +1,143 @@
-use diesel::result::Error as DatabaseError;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Formatter;
 
+use diesel::result::Error as QueryError;
+
+use crate::bot::language::Language;
+use crate::db::error::DbError;
+use crate::log::redact::Text;
 use crate::telegram::error::TelegramError;
-use r2d2::Error as DatabaseConnectionError;
-use std::num::ParseFloatError;
 
 #[derive(Debug)]
 pub enum BotError {
     TelegramError(TelegramError),
     UnrecognisedCommand(String),
-    DatabaseError(DatabaseError),
-    ParsingError(ParseFloatError),
-    DatabaseConnectionError(DatabaseConnectionError),
+    DatabaseError(DbError),
+    /// input sent by the user can not be accepted, e.g. amount which is not a number,
+    /// the input is quoted in the reply and redacted in logs
+    ValidationError(String),
+    /// command is available only after registration via /start
+    NotRegistered,
+    /// record the command operates on does not exist
+    NotFound(String),
+    /// flood control of Telegram has not let the reply through, it can be sent after
+    /// the given number of seconds
+    RateLimited(u64),
+    /// user is not allowed to run the command, e.g. it is disabled for everyone but admins
+    PermissionDenied(String),
+    /// dialog kept in the store can not be continued, e.g. its command is unknown
+    CorruptedDialog(String),
+    /// handling of the update has panicked, the message of the panic is kept
+    Panicked(String),
+    ChartError(String),
+    /// HTTP server receiving updates via webhook has failed, e.g. its address is taken
+    WebhookError(String),
 }
 
-impl From<TelegramError> for BotError {
-    fn from(error: TelegramError) -> Self {
-        BotError::TelegramError(error)
+impl BotError {
+    /// name of the variant, errors are counted by it
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BotError::TelegramError(_) => "TelegramError",
+            BotError::UnrecognisedCommand(_) => "UnrecognisedCommand",
+            BotError::DatabaseError(_) => "DatabaseError",
+            BotError::ValidationError(_) => "ValidationError",
+            BotError::NotRegistered => "NotRegistered",
+            BotError::NotFound(_) => "NotFound",
+            BotError::RateLimited(_) => "RateLimited",
+            BotError::PermissionDenied(_) => "PermissionDenied",
+            BotError::CorruptedDialog(_) => "CorruptedDialog",
+            BotError::Panicked(_) => "Panicked",
+            BotError::ChartError(_) => "ChartError",
+            BotError::WebhookError(_) => "WebhookError",
+        }
     }
-}
 
-impl From<DatabaseError> for BotError {
-    fn from(error: DatabaseError) -> Self {
-        BotError::DatabaseError(error)
+    /// reply explaining the user what went wrong, details of internal errors are not revealed
+    pub fn user_message(&self, language: Language) -> String {
+        match (self, language) {
+            (BotError::ValidationError(input), Language::English) => {
+                format!("Can not parse: {}. Check it and try again.", input)
+            }
+            (BotError::ValidationError(input), Language::Russian) => {
+                format!(
+                    "Не удалось разобрать: {}. Проверьте и попробуйте ещё раз.",
+                    input
+                )
+            }
+            (BotError::NotRegistered, Language::English) => {
+                "Only available for registered users. Use /help to see list of available commands."
+                    .to_string()
+            }
+            (BotError::NotRegistered, Language::Russian) => {
+                "Доступно только зарегистрированным пользователям. Список команд: /help."
+                    .to_string()
+            }
+            (BotError::NotFound(_), Language::English) => {
+                "Nothing has been found. Use /history to see your records.".to_string()
+            }
+            (BotError::NotFound(_), Language::Russian) => {
+                "Ничего не найдено. Ваши записи можно посмотреть через /history.".to_string()
+            }
+            (BotError::RateLimited(seconds), Language::English) => {
+                format!("Too many requests. Try again in {} seconds.", seconds)
+            }
+            (BotError::RateLimited(seconds), Language::Russian) => format!(
+                "Слишком много запросов. Попробуйте снова через {} сек.",
+                seconds
+            ),
+            (BotError::PermissionDenied(_), Language::English) => {
+                "You are not allowed to do this.".to_string()
+            }
+            (BotError::PermissionDenied(_), Language::Russian) => {
+                "У вас нет прав на это действие.".to_string()
+            }
+            (BotError::UnrecognisedCommand(_), Language::English)
+            | (BotError::CorruptedDialog(_), Language::English) => {
+                "I did not get it. Use /help to see list of available commands.".to_string()
+            }
+            (BotError::UnrecognisedCommand(_), Language::Russian)
+            | (BotError::CorruptedDialog(_), Language::Russian) => {
+                "Я не понял. Список команд: /help.".to_string()
+            }
+            (_, Language::English) => ERROR_TEXT.to_string(),
+            (_, Language::Russian) => ERROR_TEXT_RU.to_string(),
+        }
     }
 }
 
-impl From<String> for BotError {
-    fn from(error_text: String) -> Self {
-        BotError::UnrecognisedCommand(error_text)
+const ERROR_TEXT: &str = r#"
+Looks like I'm having a technical glitch. Something went wrong.
+If the issues persist send feedback via /feedback command.
+"#;
+
+const ERROR_TEXT_RU: &str = r#"
+Похоже, у меня технический сбой. Что-то пошло не так.
+Если ошибка повторяется, напишите об этом через /feedback.
+"#;
+
+impl From<TelegramError> for BotError {
+    fn from(error: TelegramError) -> Self {
+        match error.retry_after() {
+            Some(seconds) => BotError::RateLimited(seconds),
+            None => BotError::TelegramError(error),
+        }
     }
 }
 
-impl From<ParseFloatError> for BotError {
-    fn from(parse_float_error: ParseFloatError) -> Self {
-        BotError::ParsingError(parse_float_error)
+impl From<DbError> for BotError {
+    fn from(error: DbError) -> Self {
+        match error {
+            DbError::Query(QueryError::NotFound) => BotError::NotFound(error.to_string()),
+            _ => BotError::DatabaseError(error),
+        }
     }
 }
 
-impl From<DatabaseConnectionError> for BotError {
-    fn from(r2d2_error: DatabaseConnectionError) -> Self {
-        BotError::DatabaseConnectionError(r2d2_error)
+impl From<String> for BotError {
+    fn from(error_text: String) -> Self {
+        BotError::UnrecognisedCommand(error_text)
     }
 }
 
@@ -54,8 +149,77 @@ impl fmt::Display for BotError {
             BotError::TelegramError(err) => err.fmt(f),
             BotError::DatabaseError(err) => err.fmt(f),
             BotError::UnrecognisedCommand(err) => err.fmt(f),
-            BotError::ParsingError(err) => err.fmt(f),
-            BotError::DatabaseConnectionError(err) => err.fmt(f),
+            BotError::ValidationError(input) => write!(f, "invalid input: {}", Text(input)),
+            BotError::NotRegistered => write!(f, "user is not registered"),
+            BotError::NotFound(what) => write!(f, "not found: {}", what),
+            BotError::RateLimited(seconds) => {
+                write!(f, "rate limited, retry after {} seconds", seconds)
+            }
+            BotError::PermissionDenied(reason) => write!(f, "permission denied: {}", reason),
+            BotError::CorruptedDialog(reason) => write!(f, "corrupted dialog: {}", reason),
+            BotError::Panicked(message) => write!(f, "panicked: {}", message),
+            BotError::ChartError(err) => err.fmt(f),
+            BotError::WebhookError(err) => write!(f, "webhook server failed: {}", err),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_are_localized() {
+        let error = BotError::RateLimited(5);
+
+        assert_eq!(
+            error.user_message(Language::English),
+            "Too many requests. Try again in 5 seconds."
+        );
+        assert_eq!(
+            error.user_message(Language::Russian),
+            "Слишком много запросов. Попробуйте снова через 5 сек."
+        );
+    }
+
+    #[test]
+    fn internal_errors_are_not_revealed() {
+        let error = BotError::from(DbError::Query(QueryError::RollbackTransaction));
+
+        assert_eq!(error.kind(), "DatabaseError");
+        assert_eq!(error.user_message(Language::English), ERROR_TEXT);
+    }
+
+    #[test]
+    fn invalid_input_is_not_logged() {
+        let error = BotError::ValidationError("card 4111 1111 1111 1111".to_string());
+
+        assert_eq!(error.to_string(), "invalid input: <24 chars>");
+        assert!(error
+            .user_message(Language::English)
+            .contains("card 4111 1111 1111 1111"));
+    }
+
+    #[test]
+    fn flood_wait_is_rate_limited() {
+        let error = serde_json::from_str(
+            r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 90","parameters":{"retry_after":90}}"#,
+        )
+        .unwrap();
+
+        let error = BotError::from(TelegramError::Api(error));
+
+        assert_eq!(error.kind(), "RateLimited");
+        assert_eq!(
+            error.user_message(Language::English),
+            "Too many requests. Try again in 90 seconds."
+        );
+    }
+
+    #[test]
+    fn missing_record_is_not_found() {
+        let error = BotError::from(DbError::from(QueryError::NotFound));
+
+        assert_eq!(error.kind(), "NotFound");
+    }
+}
@@ -0,0 +1,183 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+
+use crate::bot::Bot;
+use crate::metrics;
+use crate::telegram::client::TelegramService;
+
+/// How long polling may go without a successful getUpdates. Each long poll lasts up to
+/// 30 seconds and a failed one is retried after 5 seconds, so a few failures are tolerated.
+const POLLING_STALE_AFTER: Duration = Duration::from_secs(120);
+
+impl<T: TelegramService> Bot<T> {
+    /// fails when polling has stalled, the process has to be restarted then
+    pub fn check_liveness(&self) -> Result<(), String> {
+        let polling_since = *self
+            .polling_since
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match polling_since {
+            Some(since) => {
+                let last_poll = self.telegram_client.last_poll().unwrap_or(since);
+                if last_poll.elapsed() > POLLING_STALE_AFTER {
+                    Err(format!(
+                        "no successful poll for {} seconds",
+                        last_poll.elapsed().as_secs()
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// fails when the bot can not handle updates: it shuts down, the store is unreachable
+    /// or polling has stalled
+    pub async fn check_readiness(&self) -> Result<(), String> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err("shutting down".to_string());
+        }
+        if let Err(err) = self.store.ping().await {
+            return Err(format!("store is unreachable: {}", err));
+        }
+        self.check_liveness()
+    }
+}
+
+/// serves `/healthz` and `/readyz` probes along with `/metrics` until shutdown
+pub async fn serve<T, F>(
+    address: SocketAddr,
+    bot: Arc<Bot<T>>,
+    shutdown: F,
+) -> Result<(), hyper::Error>
+where
+    T: TelegramService + 'static,
+    F: Future<Output = ()>,
+{
+    let make_service = make_service_fn(move |_| {
+        let bot = bot.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, bot.clone())
+            }))
+        }
+    });
+
+    info!("serving health checks on {}", address);
+    Server::bind(&address)
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+async fn handle_request<T: TelegramService>(
+    request: Request<Body>,
+    bot: Arc<Bot<T>>,
+) -> Result<Response<Body>, Infallible> {
+    let check = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            return Ok(text_response(StatusCode::OK, &metrics::render()));
+        }
+        (&Method::GET, "/healthz") => bot.check_liveness(),
+        (&Method::GET, "/readyz") => bot.check_readiness().await,
+        _ => return Ok(text_response(StatusCode::NOT_FOUND, "not found")),
+    };
+    match check {
+        Ok(()) => Ok(text_response(StatusCode::OK, "ok")),
+        Err(reason) => {
+            error!("{} check failed: {}", request.uri().path(), reason);
+            Ok(text_response(StatusCode::SERVICE_UNAVAILABLE, &reason))
+        }
+    }
+}
+
+fn text_response(status: StatusCode, text: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(text.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, stream};
+
+    use super::*;
+    use crate::db::memory::InMemoryStore;
+    use crate::telegram::fake::RecordingTelegramClient;
+    use crate::telegram::types::Update;
+
+    fn request(path: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::GET)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn bot() -> Arc<Bot<RecordingTelegramClient>> {
+        Arc::new(Bot::with_store(
+            RecordingTelegramClient::new("expenses_bot"),
+            Arc::new(InMemoryStore::new()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn running_bot_is_healthy_and_ready() {
+        let bot = bot();
+
+        for path in &["/healthz", "/readyz"] {
+            let response = handle_request(request(path), bot.clone()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = handle_request(request("/unknown"), bot).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn metrics_are_served() {
+        let bot = bot();
+        bot.process_updates(
+            stream::iter(vec![Update {
+                update_id: 1,
+                message: None,
+                callback_query: None,
+            }]),
+            None,
+            future::pending(),
+            false,
+        )
+        .await;
+
+        let response = handle_request(request("/metrics"), bot).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"bot_updates_received_total{kind="other"}"#));
+        assert!(body.contains("bot_handler_duration_seconds_count"));
+    }
+
+    #[tokio::test]
+    async fn draining_bot_is_not_ready() {
+        let bot = bot();
+
+        bot.process_updates(stream::pending(), None, future::ready(()), false)
+            .await;
+
+        let response = handle_request(request("/readyz"), bot.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = handle_request(request("/healthz"), bot).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
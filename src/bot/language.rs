@@ -0,0 +1,35 @@
+/// Language replies are written in, chosen by the language of the user's Telegram app.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    English,
+    Russian,
+}
+
+impl Language {
+    /// language for the IETF language tag sent by Telegram, English unless it is supported
+    pub fn from_code(language_code: Option<&str>) -> Self {
+        match language_code {
+            Some(code) if code == "ru" || code.starts_with("ru-") => Language::Russian,
+            _ => Language::English,
+        }
+    }
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Language::English
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_is_chosen_by_code() {
+        assert_eq!(Language::from_code(Some("ru")), Language::Russian);
+        assert_eq!(Language::from_code(Some("ru-RU")), Language::Russian);
+        assert_eq!(Language::from_code(Some("de")), Language::English);
+        assert_eq!(Language::from_code(None), Language::English);
+    }
+}
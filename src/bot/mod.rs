@@ -1,26 +1,56 @@
+use std::convert::TryFrom;
 use std::fmt;
+use std::panic::AssertUnwindSafe;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use futures::StreamExt;
+use diesel::result::Error as QueryError;
+use futures::future::{self, join, join_all, Either};
+use futures::{pin_mut, Future, FutureExt, Stream, StreamExt};
 use log::{error, info};
-use telegram_bot::{MessageKind, UpdateKind};
+use tokio::sync::mpsc::{channel, unbounded_channel};
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::{field, info_span, Instrument, Span};
 
 use crate::{
     bot::{
-        dialogs::{Add, Command, Dialog, Feedback, History, Start},
+        dialogs::{
+            Add, Chart, Command, Dialog, Feedback, History, Join, Leave, Settle, Share, Start,
+        },
         error::BotError,
+        language::Language,
+        updates::UpdateTracker,
+    },
+    config::Config,
+    db::{
+        database::Database, error::DbError, models::dialog::DialogEntity, store::Store,
+        DbConnectionPool,
+    },
+    log::redact::{Id, Text},
+    metrics,
+    telegram::{
+        client::{TelegramClient, TelegramService},
+        types::{AnswerCallbackQuery, EditMessage, Message, ReceivedMessage, Update},
+        webhook::{self, WebhookConfig},
     },
-    db::{models::dialog::DialogEntity, Connection, DbConnectionPool},
-    telegram::{client::TelegramClient, types::Message},
 };
 
+pub mod charts;
 pub mod dialogs;
 pub mod error;
+pub mod health;
+pub mod language;
+pub mod shutdown;
+pub mod split;
+pub mod updates;
 
-const ERROR_TEXT: &str = r#"
-Looks like I'm having a technical glitch. Something went wrong.
-If the issues persist send feedback via /feedback command.
-"#;
+/// Number of updates processed at the same time.
+const WORKERS: usize = 16;
+
+/// Number of updates waiting for each worker, polling pauses while the queue is full.
+const WORKER_QUEUE_SIZE: usize = 64;
 
 const HELP_TEXT: &str = r#"
 You can send me these commands:
@@ -29,176 +59,1379 @@ You can send me these commands:
 /help
 /history
 /add
+/share
+/join
+/leave
+/settle
+/chart
 
 If you encounter any issues feel free to open an issue.
 Or you can also send feedback via /feedback command.
 "#;
 
-pub struct Bot {
-    connection_pool: DbConnectionPool,
-    telegram_client: TelegramClient,
+const GROUP_LEDGER_TEXT: &str =
+    "Records added in this group are shared with all of its members. Use /history to see them.";
+
+pub struct Bot<T: TelegramService = TelegramClient> {
+    store: Arc<dyn Store>,
+    telegram_client: T,
+    /// when long polling has started, it is not started in webhook mode
+    polling_since: Mutex<Option<Instant>>,
+    /// set once shutdown has begun, no new updates are received after that
+    draining: AtomicBool,
+    /// updates being handled, the lock is held while the offset is kept, so offsets are
+    /// kept in the order they move
+    updates: AsyncMutex<UpdateTracker>,
+    /// offset polling continues from, it moves past updates once they are handled
+    confirmed_offset: Arc<AtomicI64>,
+    /// commands only admins may use
+    disabled_commands: Vec<Command>,
+    admin_ids: Vec<String>,
 }
 
-impl fmt::Debug for Bot {
+impl<T: TelegramService + fmt::Debug> fmt::Debug for Bot<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{:?}", self.telegram_client)
     }
 }
 
 impl Bot {
-    pub fn new(token: &str, db_url: &str) -> Self {
-        let connection_pool = DbConnectionPool::new(db_url);
-        let telegram_client = TelegramClient::new(token.to_string());
+    /// fails when the proxy to Telegram can not be used
+    pub fn new(config: &Config) -> Result<Self, BotError> {
+        let mut telegram_client = TelegramClient::new_with(
+            config.telegram_token.clone(),
+            config.telegram_api_url.clone(),
+        );
+        if let Some(proxy) = &config.proxy {
+            telegram_client = telegram_client.with_proxy(proxy)?;
+        }
+        let pool = DbConnectionPool::with_pool_size(&config.database_url, config.pool_size);
+        let store = Database::new(pool);
+        Ok(Self::with_store(telegram_client, Arc::new(store))
+            .with_disabled_commands(config.disabled_commands.clone(), &config.admin_ids))
+    }
+}
+
+impl<T: TelegramService> Bot<T> {
+    /// bot talking to Telegram via the given service and keeping its data in the given store,
+    /// e.g. the recording fake and memory in tests
+    pub fn with_store(telegram_client: T, store: Arc<dyn Store>) -> Self {
         Bot {
-            connection_pool,
+            store,
             telegram_client,
+            polling_since: Mutex::new(None),
+            draining: AtomicBool::new(false),
+            updates: AsyncMutex::new(UpdateTracker::new()),
+            confirmed_offset: Arc::new(AtomicI64::new(0)),
+            disabled_commands: vec![],
+            admin_ids: vec![],
         }
     }
 
-    pub async fn init_bot(&self) {
-        let mut stream = self.telegram_client.stream();
-        while let Some(update) = stream.next().await {
-            if let Ok(update) = update {
-                match update.kind {
-                    UpdateKind::Message(message) => {
-                        if let MessageKind::Text { data, .. } = message.kind {
-                            let user_id = message.from.id.to_string();
-                            if let Err(e) = self.handle_message(data, &user_id).await {
-                                error!("error handling message: {}", e);
-                                match e {
-                                    BotError::ParsingError(_) => (),
-                                    _ => {
-                                        let _ = self
-                                            .telegram_client
-                                            .send_message(&Message {
-                                                chat_id: &user_id,
-                                                text: ERROR_TEXT,
-                                                ..Default::default()
-                                            })
-                                            .await
-                                            .ok();
-                                    }
-                                }
-                            }
-                        }
+    /// turns the commands off for everyone but the admins
+    pub fn with_disabled_commands(mut self, commands: Vec<Command>, admin_ids: &[i64]) -> Self {
+        self.disabled_commands = commands;
+        self.admin_ids = admin_ids.iter().map(i64::to_string).collect();
+        self
+    }
+
+    /// receives updates via long polling until shutdown, then finishes handling received ones
+    pub async fn init_bot<F>(&self, shutdown: F)
+    where
+        F: Future<Output = ()>,
+    {
+        let bot_username = self.bot_username().await;
+        if let Err(err) = self.telegram_client.delete_webhook().await {
+            error!("failed to delete webhook: {}", err);
+        }
+        self.confirmed_offset
+            .store(self.polling_offset().await, Ordering::SeqCst);
+        *self
+            .polling_since
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Instant::now());
+        let updates = self
+            .telegram_client
+            .stream(self.confirmed_offset.clone())
+            .filter_map(|update| async move {
+                match update {
+                    Ok(update) => Some(update),
+                    Err(err) => {
+                        error!("failed to receive update: {}", err);
+                        None
                     }
-                    UpdateKind::CallbackQuery(query) => {
-                        if query.message.is_none() {
-                            info!("empty message in callback query");
-                            continue;
-                        }
+                }
+            });
+        self.process_updates(updates, bot_username.as_deref(), shutdown, false)
+            .await;
+    }
+
+    /// receives updates sent by Telegram to the embedded HTTP server until shutdown,
+    /// then finishes handling received ones. Failure of the server is returned once
+    /// the updates it has received are handled.
+    pub async fn init_webhook<F>(&self, config: WebhookConfig, shutdown: F) -> Result<(), BotError>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let bot_username = self.bot_username().await;
+        self.telegram_client
+            .set_webhook(&config.url, &config.secret_token)
+            .await?;
+
+        let shutdown = shutdown.shared();
+        let (sender, receiver) = unbounded_channel();
+        let server = tokio::spawn(webhook::listen(
+            config.address,
+            config.secret_token,
+            sender,
+            shutdown.clone(),
+        ));
+        // the server answers requests it has accepted before it stops, so updates are taken
+        // until it drops the sender, otherwise updates Telegram considers delivered are lost
+        self.process_updates(receiver, bot_username.as_deref(), shutdown, true)
+            .await;
+        match server.await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(BotError::WebhookError(err.to_string())),
+            Err(err) => Err(BotError::WebhookError(err.to_string())),
+        }
+    }
 
-                        if query.data.is_none() {
-                            info!("empty data in callback query");
+    /// processes updates concurrently by a fixed number of workers, updates of the same user
+    /// are always processed by the same worker, so they are handled in the order they came.
+    /// On shutdown no more updates are taken, workers finish the ones already queued.
+    /// When `until_closed` is set, updates are taken after shutdown until the stream ends.
+    async fn process_updates<S, F>(
+        &self,
+        updates: S,
+        bot_username: Option<&str>,
+        shutdown: F,
+        until_closed: bool,
+    ) where
+        S: Stream<Item = Update>,
+        F: Future<Output = ()>,
+    {
+        let (mut senders, receivers): (Vec<_>, Vec<_>) = (0..WORKERS)
+            .map(|_| channel::<Update>(WORKER_QUEUE_SIZE))
+            .unzip();
+
+        let dispatcher = async move {
+            pin_mut!(updates);
+            pin_mut!(shutdown);
+            let mut shutting_down = false;
+            loop {
+                let next = if shutting_down {
+                    updates.next().await
+                } else {
+                    // shutdown is checked first, so it is not delayed by a steady flow of updates
+                    match future::select(shutdown.as_mut(), updates.next()).await {
+                        Either::Left(_) => {
+                            info!("shutting down, handling received updates");
+                            self.draining.store(true, Ordering::SeqCst);
+                            if !until_closed {
+                                break;
+                            }
+                            shutting_down = true;
                             continue;
                         }
-                        let data = query.data.expect("There is no data at callback query");
-                        let user_id = query.from.id.to_string();
-
-                        if let Err(e) = self.handle_message(data, &user_id).await {
-                            error!("error handling message: {}", e);
-                            let error_message = Message {
-                                chat_id: &user_id,
-                                text: ERROR_TEXT,
-                                ..Default::default()
-                            };
-                            let _ = self.telegram_client.send_message(&error_message).await.ok();
-                        }
+                        Either::Right((next, _)) => next,
                     }
-                    _ => {}
+                };
+                let update = match next {
+                    Some(update) => update,
+                    None => break,
+                };
+                metrics::UPDATES_RECEIVED
+                    .with_label_values(&[update_kind(&update)])
+                    .inc();
+                self.updates.lock().await.receive(update.update_id);
+                let worker = worker_index(&update, WORKERS);
+                if senders[worker].send(update).await.is_err() {
+                    error!("worker {} has stopped", worker);
+                    break;
+                }
+            }
+        };
+        let workers = receivers.into_iter().map(|mut receiver| async move {
+            while let Some(update) = receiver.recv().await {
+                let update_id = update.update_id;
+                let span = update_span(&update);
+                self.handle_update_isolated(update, bot_username)
+                    .instrument(span)
+                    .await;
+                self.finish_update(update_id).await;
+            }
+        });
+
+        let _ = join(dispatcher, join_all(workers)).await;
+    }
+
+    async fn bot_username(&self) -> Option<String> {
+        match self.telegram_client.get_me().await {
+            Ok(username) => Some(username),
+            Err(err) => {
+                error!(
+                    "failed to get bot username, commands mentioning any bot will be handled: {}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// handles the update, a panic while handling it is reported to the user and their dialog
+    /// is reset instead of the worker being stopped
+    async fn handle_update_isolated(&self, update: Update, bot_username: Option<&str>) {
+        let update_id = update.update_id;
+        let origin = update_origin(&update);
+        let message = match catch_panic(self.handle_update(update, bot_username)).await {
+            Ok(()) => return,
+            Err(message) => message,
+        };
+        match origin {
+            Some((chat_id, user_id, language)) => {
+                self.reset_dialog(&chat_id, &user_id).await;
+                self.report_error(&chat_id, BotError::Panicked(message), language)
+                    .await;
+            }
+            None => error!("handling of update {} has panicked: {}", update_id, message),
+        }
+    }
+
+    /// ends the dialog the user is in, so the next message does not continue the failed one.
+    /// Registered users get the dialog finished dialogs leave, others get no dialog at all,
+    /// so they are not told they have registered already.
+    async fn reset_dialog(&self, chat_id: &str, user_id: &str) {
+        if self.store.get_user_dialog(chat_id, user_id).await.is_err() {
+            return;
+        }
+        let reset = match self.store.is_registered(user_id).await {
+            Ok(true) => {
+                let dialog = DialogEntity::new(
+                    chat_id.to_string(),
+                    user_id.to_string(),
+                    Command::Start.to_string(),
+                    None,
+                );
+                self.store.save_dialog(dialog).await
+            }
+            Ok(false) => self.store.delete_dialog(chat_id, user_id).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = reset {
+            error!("failed to reset dialog of user {}: {}", Id(user_id), err);
+        }
+    }
+
+    async fn handle_update(&self, update: Update, bot_username: Option<&str>) {
+        let _timer = metrics::HANDLER_DURATION.start_timer();
+        if let Some(message) = update.message {
+            let (from, data) = match (message.from, message.text) {
+                (Some(from), Some(data)) => (from, data),
+                _ => return,
+            };
+            let chat_id = message.chat.id.to_string();
+            let user_id = from.id.to_string();
+            let language = Language::from_code(from.language_code.as_deref());
+            self.remember_names(&user_id, from.username.as_deref(), &from.first_name)
+                .await;
+            let payload = match strip_bot_mention(&data, bot_username) {
+                Some(payload) => payload,
+                None => {
+                    info!("message is addressed to another bot: {}", Text(&data));
+                    return;
+                }
+            };
+            if let Err(e) = self
+                .handle_message_once(update.update_id, payload, &chat_id, &user_id)
+                .await
+            {
+                self.report_error(&chat_id, e, language).await;
+            }
+        } else if let Some(query) = update.callback_query {
+            self.answer_callback_query(&query.id).await;
+            let message = match query.message {
+                Some(message) => message,
+                None => {
+                    info!("empty message in callback query");
+                    return;
+                }
+            };
+
+            let data = match query.data {
+                Some(data) => data,
+                None => {
+                    info!("empty data in callback query");
+                    return;
                 }
+            };
+            let chat_id = message.chat.id.to_string();
+            let user_id = query.from.id.to_string();
+            let language = Language::from_code(query.from.language_code.as_deref());
+            let owns_keyboard = self
+                .owns_keyboard(&chat_id, &user_id, message.message_id)
+                .await;
+
+            match self
+                .handle_message_once(update.update_id, data.clone(), &chat_id, &user_id)
+                .await
+            {
+                // duplicates and presses ignored in groups leave the keyboard as it is
+                Ok(Some(_)) if owns_keyboard => self.close_keyboard(&message, &data).await,
+                Ok(_) => {}
+                Err(e) => self.report_error(&chat_id, e, language).await,
             }
         }
     }
 
-    pub async fn handle_message(&self, payload: String, user_id: &str) -> Result<String, BotError> {
-        info!("received message from: {}, message: {}", user_id, payload);
+    /// stops the progress indicator on the pressed button, Telegram shows it until the query
+    /// is answered
+    async fn answer_callback_query(&self, query_id: &str) {
+        let answer = AnswerCallbackQuery {
+            callback_query_id: query_id,
+            ..Default::default()
+        };
+        if let Err(err) = self.telegram_client.answer_callback_query(&answer).await {
+            error!("failed to answer callback query: {}", err);
+        }
+    }
 
-        let connection = self.connection_pool.establish_connection();
+    /// whether the keyboard has been sent for the current step of the user's dialog, in groups
+    /// other members may press it too
+    async fn owns_keyboard(&self, chat_id: &str, user_id: &str, message_id: i64) -> bool {
+        match self.store.get_user_dialog(chat_id, user_id).await {
+            Ok(dialog) => dialog.keyboard_message_id == Some(message_id),
+            Err(DbError::Query(QueryError::NotFound)) => false,
+            Err(err) => {
+                error!("failed to get dialog of user {}: {}", Id(user_id), err);
+                false
+            }
+        }
+    }
 
-        let sent_text_message = match payload.as_ref() {
+    /// replaces the keyboard of the message by the chosen option, so the choice is visible
+    /// and the buttons can not be pressed again
+    async fn close_keyboard(&self, message: &ReceivedMessage, choice: &str) {
+        let text = match &message.text {
+            Some(text) => format!("{}: {}", text, choice),
+            None => return,
+        };
+        let chat_id = message.chat.id.to_string();
+        let message_id = message.message_id.to_string();
+        let edited = self
+            .telegram_client
+            .edit_message_text(&EditMessage {
+                chat_id: &chat_id,
+                message_id: &message_id,
+                text: &text,
+                ..Default::default()
+            })
+            .await;
+        if let Err(err) = edited {
+            error!("failed to close keyboard in chat {}: {}", Id(&chat_id), err);
+        }
+    }
+
+    /// logs the error and explains it to the user in their language. Rate limited chats are
+    /// not replied, the reply would be rejected by Telegram too.
+    async fn report_error(&self, chat_id: &str, error: BotError, language: Language) {
+        error!("error handling message: {}", error);
+        metrics::BOT_ERRORS.with_label_values(&[error.kind()]).inc();
+        if let BotError::RateLimited(_) = error {
+            return;
+        }
+        let text = error.user_message(language);
+        let sent = self
+            .telegram_client
+            .send_message(&Message {
+                chat_id,
+                text: &text,
+                ..Default::default()
+            })
+            .await;
+        if let Err(err) = sent {
+            error!("failed to report error to chat {}: {}", Id(chat_id), err);
+        }
+    }
+
+    /// continues polling from the oldest update which has not been handled, so no updates
+    /// are lost on restart. Newer updates handled before it are skipped by their claims.
+    async fn polling_offset(&self) -> i64 {
+        match self.store.next_update_id().await {
+            Ok(next_update_id) => next_update_id.unwrap_or(0),
+            Err(err) => {
+                error!("failed to get offset of updates: {}", err);
+                0
+            }
+        }
+    }
+
+    /// marks the update as handled and keeps the offset once all older updates are handled too.
+    /// Claims of updates before the offset are forgotten, Telegram does not deliver them again.
+    async fn finish_update(&self, update_id: i64) {
+        let mut updates = self.updates.lock().await;
+        let next_update_id = match updates.finish(update_id) {
+            Some(next_update_id) => next_update_id,
+            None => return,
+        };
+        self.confirmed_offset
+            .store(next_update_id, Ordering::SeqCst);
+        if let Err(err) = self.store.confirm_updates(next_update_id).await {
+            error!("failed to keep offset of updates: {}", err);
+            return;
+        }
+        if let Err(err) = self.store.prune_updates(next_update_id).await {
+            error!("failed to prune processed updates: {}", err);
+        }
+    }
+
+    /// handles message of the update unless it has been processed already, e.g. when
+    /// Telegram delivers the update again after restart. The update is claimed only once it
+    /// has been handled, so an update interrupted by a failure or a crash is handled again.
+    async fn handle_message_once(
+        &self,
+        update_id: i64,
+        payload: String,
+        chat_id: &str,
+        user_id: &str,
+    ) -> Result<Option<ReceivedMessage>, BotError> {
+        if self.store.is_update_processed(update_id).await? {
+            info!("update {} has already been processed", update_id);
+            return Ok(None);
+        }
+        let result = self.handle_message(payload, chat_id, user_id).await;
+        if result.is_ok() {
+            if let Err(err) = self.store.claim_update(update_id).await {
+                error!("failed to claim update {}: {}", update_id, err);
+            }
+        }
+        result
+    }
+
+    /// keeps names of the user up to date, so members can mention the user in splits
+    /// and see who has spent what in reports
+    async fn remember_names(&self, user_id: &str, username: Option<&str>, first_name: &str) {
+        if let Err(err) = self.store.update_names(username, first_name, user_id).await {
+            error!("failed to remember names of user {}: {}", Id(user_id), err);
+        }
+    }
+
+    /// handles message sent by the user to the chat, for private chats `chat_id` equals `user_id`.
+    /// Returns the reply of the bot, nothing is sent to messages not addressed to the bot.
+    pub async fn handle_message(
+        &self,
+        payload: String,
+        chat_id: &str,
+        user_id: &str,
+    ) -> Result<Option<ReceivedMessage>, BotError> {
+        info!(
+            "received message from: {} in chat: {}, message: {}",
+            Id(user_id),
+            Id(chat_id),
+            Text(&payload)
+        );
+
+        let (command, arguments) = split_command(&payload);
+        if let Ok(command) = Command::from_str(command) {
+            count_command(command);
+            if self.disabled_commands.contains(&command)
+                && !self.admin_ids.iter().any(|admin_id| admin_id == user_id)
+            {
+                return Err(BotError::PermissionDenied(format!(
+                    "{} is disabled",
+                    command
+                )));
+            }
+        }
+        let is_registered = match command {
+            "/share" | "/join" | "/leave" | "/settle" | "/chart" => {
+                self.store.is_registered(user_id).await?
+            }
+            _ => true,
+        };
+        let store = self.store.as_ref();
+        let sent_message = match command {
             "/start" => {
                 Dialog::<Start>::new()
-                    .handle_current_step(&connection, &self.telegram_client, user_id, "")
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
                     .await?
             }
             "/feedback" => {
                 Dialog::<Feedback>::new()
-                    .handle_current_step(&connection, &self.telegram_client, user_id, "")
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
                     .await?
             }
             "/help" => {
-                let sent_text = &self
-                    .telegram_client
+                self.telegram_client
                     .send_message(&Message {
-                        chat_id: user_id,
+                        chat_id,
                         text: HELP_TEXT,
                         ..Default::default()
                     })
-                    .await?;
-                sent_text.to_owned()
+                    .await?
             }
             "/add" => {
                 Dialog::<Add>::new()
-                    .handle_current_step(&connection, &self.telegram_client, user_id, "")
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, arguments)
                     .await?
             }
             "/history" => {
                 Dialog::<History>::new()
-                    .handle_current_step(&connection, &self.telegram_client, user_id, "")
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
                     .await?
             }
-            _ => {
-                handle_not_a_command_message(&connection, &self.telegram_client, &user_id, &payload)
+            "/share" | "/join" | "/leave" if chat_id != user_id => {
+                self.telegram_client
+                    .send_message(&Message {
+                        chat_id,
+                        text: GROUP_LEDGER_TEXT,
+                        ..Default::default()
+                    })
+                    .await?
+            }
+            "/share" | "/join" | "/leave" | "/settle" | "/chart" if !is_registered => {
+                return Err(BotError::NotRegistered);
+            }
+            "/share" => {
+                Dialog::<Share>::new()
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
+                    .await?
+            }
+            "/join" => {
+                Dialog::<Join>::new()
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
+                    .await?
+            }
+            "/leave" => {
+                Dialog::<Leave>::new()
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
+                    .await?
+            }
+            "/settle" => {
+                Dialog::<Settle>::new()
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
                     .await?
             }
+            "/chart" => {
+                Dialog::<Chart>::new()
+                    .handle_current_step(store, &self.telegram_client, chat_id, user_id, "")
+                    .await?
+            }
+            _ => {
+                return handle_not_a_command_message(
+                    store,
+                    &self.telegram_client,
+                    chat_id,
+                    user_id,
+                    &payload,
+                )
+                .await
+            }
         };
-        Ok(sent_text_message)
+        Ok(Some(sent_message))
     }
 }
 
-/// process if this message received from registered user else send don't get message
+/// process if this message received from registered user else send don't get message.
+/// In group chats only messages of users who are in the middle of a dialog are processed.
 async fn handle_not_a_command_message(
-    conn: &Connection,
-    telegram_client: &TelegramClient,
+    db: &dyn Store,
+    telegram_client: &dyn TelegramService,
+    chat_id: &str,
     user_id: &str,
     payload: &str,
-) -> Result<String, BotError> {
-    match DialogEntity::get_user_dialog(user_id, conn) {
+) -> Result<Option<ReceivedMessage>, BotError> {
+    let is_group = chat_id != user_id;
+    match db.get_user_dialog(chat_id, user_id).await {
+        Ok(dialog_entity) if is_group && !awaits_input(&dialog_entity) => {
+            info!("message in chat {} is not addressed to bot", Id(chat_id));
+            Ok(None)
+        }
         Ok(dialog_entity) => {
-            let command = Command::from_str(&dialog_entity.command).expect("Can not process command. Problem with dialog entity probably");
+            let command = Command::from_str(&dialog_entity.command).map_err(|_| {
+                BotError::CorruptedDialog(format!("unknown command {}", dialog_entity.command))
+            })?;
+            count_command(command);
 
             match command {
                 Command::Start => {
-                    let dialog: Dialog<Start> = dialog_entity.into();
-                    Ok(dialog
-                        .handle_current_step(conn, telegram_client, user_id, payload)
-                        .await?)
+                    let dialog = Dialog::<Start>::try_from(dialog_entity)?;
+                    Ok(Some(
+                        dialog
+                            .handle_current_step(db, telegram_client, chat_id, user_id, payload)
+                            .await?,
+                    ))
                 }
                 Command::Feedback => {
-                    let dialog: Dialog<Feedback> = dialog_entity.into();
-                    Ok(dialog
-                        .handle_current_step(conn, telegram_client, user_id, payload)
-                        .await?)
+                    let dialog = Dialog::<Feedback>::try_from(dialog_entity)?;
+                    Ok(Some(
+                        dialog
+                            .handle_current_step(db, telegram_client, chat_id, user_id, payload)
+                            .await?,
+                    ))
                 }
                 Command::Add => {
-                    let dialog: Dialog<Add> = dialog_entity.into();
-                    Ok(dialog
-                        .handle_current_step(conn, telegram_client, user_id, payload)
-                        .await?)
+                    let dialog = Dialog::<Add>::try_from(dialog_entity)?;
+                    Ok(Some(
+                        dialog
+                            .handle_current_step(db, telegram_client, chat_id, user_id, payload)
+                            .await?,
+                    ))
                 }
-                _ => {Err(BotError::UnrecognisedCommand("can not process such command".to_string()))}
+                Command::Join => {
+                    let dialog = Dialog::<Join>::try_from(dialog_entity)?;
+                    Ok(Some(
+                        dialog
+                            .handle_current_step(db, telegram_client, chat_id, user_id, payload)
+                            .await?,
+                    ))
+                }
+                Command::Chart => {
+                    let dialog = Dialog::<Chart>::try_from(dialog_entity)?;
+                    Ok(Some(
+                        dialog
+                            .handle_current_step(db, telegram_client, chat_id, user_id, payload)
+                            .await?,
+                    ))
+                }
+                _ => Err(BotError::UnrecognisedCommand(
+                    "can not process such command".to_string(),
+                )),
             }
         }
-        Err(_) => {
-            Ok(
-                telegram_client
-                    .send_message(&Message {
-                        chat_id: &user_id,
-                        text: "Only available for registered users. Use /help to see list of available commands.",
-                        ..Default::default()
-                    })
-                    .await?)
+        Err(DbError::Query(QueryError::NotFound)) if is_group => Ok(None),
+        Err(DbError::Query(QueryError::NotFound)) => Err(BotError::NotRegistered),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// whether the user is expected to send something to continue the dialog, e.g. amount of expense
+fn awaits_input(dialog: &DialogEntity) -> bool {
+    match &dialog.step {
+        Some(step) => *step != Start::AlreadyRegistered.to_string(),
+        None => false,
+    }
+}
+
+/// counts the command and attaches it to the span of the update being handled
+fn count_command(command: Command) {
+    let _ = Span::current().record("command", &format!("{:?}", command).as_str());
+    metrics::COMMANDS_HANDLED
+        .with_label_values(&[&format!("{:?}", command)])
+        .inc();
+}
+
+/// kind of the update, updates received are counted by it
+fn update_kind(update: &Update) -> &'static str {
+    match (&update.message, &update.callback_query) {
+        (Some(_), _) => "message",
+        (None, Some(_)) => "callback_query",
+        (None, None) => "other",
+    }
+}
+
+/// runs the future, a panic inside of it is returned as its message instead of unwinding
+async fn catch_panic<F: Future<Output = ()>>(future: F) -> Result<(), String> {
+    AssertUnwindSafe(future)
+        .catch_unwind()
+        .await
+        .map_err(
+            |panic| match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+                (Some(message), _) => message.to_string(),
+                (None, Some(message)) => message.clone(),
+                (None, None) => "unknown panic".to_string(),
+            },
+        )
+}
+
+/// chat the update came from, the user who sent it and their language
+fn update_origin(update: &Update) -> Option<(String, String, Language)> {
+    let (chat, from) = match (&update.message, &update.callback_query) {
+        (Some(message), _) => (&message.chat, message.from.as_ref()?),
+        (None, Some(query)) => (&query.message.as_ref()?.chat, &query.from),
+        (None, None) => return None,
+    };
+    Some((
+        chat.id.to_string(),
+        from.id.to_string(),
+        Language::from_code(from.language_code.as_deref()),
+    ))
+}
+
+/// id of the user who sent the update
+fn sender_id(update: &Update) -> Option<i64> {
+    match (&update.message, &update.callback_query) {
+        (Some(message), _) => message.from.as_ref().map(|from| from.id),
+        (None, Some(query)) => Some(query.from.id),
+        (None, None) => None,
+    }
+}
+
+/// span all log lines of handling the update belong to, the command is recorded once known
+fn update_span(update: &Update) -> Span {
+    let span = info_span!(
+        "update",
+        update_id = update.update_id,
+        user_id = field::Empty,
+        command = field::Empty
+    );
+    if let Some(user_id) = sender_id(update) {
+        let _ = span.record("user_id", &Id(user_id).to_string().as_str());
+    }
+    span
+}
+
+/// index of the worker processing updates of the user who sent the update
+fn worker_index(update: &Update, workers: usize) -> usize {
+    sender_id(update)
+        .unwrap_or(update.update_id)
+        .rem_euclid(workers as i64) as usize
+}
+
+/// separates command from its arguments, e.g. `/add 90 dinner` becomes (`/add`, `90 dinner`)
+fn split_command(payload: &str) -> (&str, &str) {
+    if !payload.starts_with('/') {
+        return (payload, "");
+    }
+    match payload.find(char::is_whitespace) {
+        Some(index) => (&payload[..index], payload[index..].trim()),
+        None => (payload, ""),
+    }
+}
+
+/// removes mention of the bot from the command, e.g. `/add@expenses_bot` becomes `/add`.
+/// Returns `None` when the command is addressed to another bot. When the username of the bot
+/// is not known, e.g. `getMe` has failed at startup, every mention is accepted, otherwise
+/// commands in groups would be ignored until restart.
+fn strip_bot_mention(text: &str, bot_username: Option<&str>) -> Option<String> {
+    if !text.starts_with('/') {
+        return Some(text.to_string());
+    }
+    let (command, rest) = match text.find(char::is_whitespace) {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    match command.find('@') {
+        None => Some(text.to_string()),
+        Some(index) => match bot_username {
+            Some(username) if !command[index + 1..].eq_ignore_ascii_case(username) => None,
+            _ => Some(format!("{}{}", &command[..index], rest)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::InMemoryStore;
+    use crate::db::store::{DialogStore, UpdateStore, UserStore};
+    use crate::telegram::fake::{RecordingTelegramClient, SentRequest};
+    use crate::telegram::types::{CallbackQuery, Chat, User};
+
+    const USER_ID: &str = "414141";
+
+    fn update(update_id: i64, user_id: Option<i64>) -> Update {
+        Update {
+            update_id,
+            message: Some(ReceivedMessage {
+                message_id: 1,
+                from: user_id.map(|id| User {
+                    id,
+                    is_bot: false,
+                    first_name: "Name".to_string(),
+                    username: None,
+                    language_code: None,
+                }),
+                chat: Chat {
+                    id: -100,
+                    type_: "group".to_string(),
+                },
+                date: 1581200384,
+                text: Some("/add".to_string()),
+                caption: None,
+                document: None,
+            }),
+            callback_query: None,
+        }
+    }
+
+    #[test]
+    fn updates_of_user_go_to_same_worker() {
+        assert_eq!(
+            worker_index(&update(1, Some(414141)), WORKERS),
+            worker_index(&update(2, Some(414141)), WORKERS)
+        );
+        assert_eq!(worker_index(&update(1, Some(17)), 16), 1);
+        assert_eq!(worker_index(&update(1, Some(18)), 16), 2);
+    }
+
+    #[test]
+    fn updates_without_user_are_spread_by_id() {
+        assert_eq!(worker_index(&update(33, None), 16), 1);
+        assert_eq!(worker_index(&update(34, None), 16), 2);
+    }
+
+    #[test]
+    fn text_is_left_as_is() {
+        assert_eq!(
+            strip_bot_mention("dinner@home", Some("expenses_bot")),
+            Some("dinner@home".to_string())
+        );
+    }
+
+    #[test]
+    fn command_without_mention_is_left_as_is() {
+        assert_eq!(
+            strip_bot_mention("/add", Some("expenses_bot")),
+            Some("/add".to_string())
+        );
+    }
+
+    #[test]
+    fn mention_of_bot_is_stripped() {
+        assert_eq!(
+            strip_bot_mention("/add@Expenses_Bot", Some("expenses_bot")),
+            Some("/add".to_string())
+        );
+        assert_eq!(
+            strip_bot_mention("/add@expenses_bot 90", Some("expenses_bot")),
+            Some("/add 90".to_string())
+        );
+    }
+
+    #[test]
+    fn command_for_another_bot_is_ignored() {
+        assert_eq!(
+            strip_bot_mention("/add@other_bot", Some("expenses_bot")),
+            None
+        );
+    }
+
+    #[test]
+    fn mention_is_accepted_when_bot_username_is_unknown() {
+        assert_eq!(
+            strip_bot_mention("/add@expenses_bot 90", None),
+            Some("/add 90".to_string())
+        );
+    }
+
+    #[test]
+    fn command_is_split_from_arguments() {
+        assert_eq!(split_command("/add"), ("/add", ""));
+        assert_eq!(
+            split_command("/add 90 dinner  split @alice "),
+            ("/add", "90 dinner  split @alice")
+        );
+        assert_eq!(split_command("90 dinner"), ("90 dinner", ""));
+    }
+
+    #[test]
+    fn dialog_in_progress_awaits_input() {
+        let dialog = DialogEntity::new(
+            "chat_id".to_string(),
+            "user_id".to_string(),
+            Command::Add.to_string(),
+            Some(Add::Amount.to_string()),
+        );
+        assert!(awaits_input(&dialog));
+    }
+
+    #[test]
+    fn finished_dialog_does_not_await_input() {
+        let finished = DialogEntity::new(
+            "chat_id".to_string(),
+            "user_id".to_string(),
+            Command::Start.to_string(),
+            None,
+        );
+        let registered = DialogEntity::new(
+            "chat_id".to_string(),
+            "user_id".to_string(),
+            Command::Start.to_string(),
+            Some(Start::AlreadyRegistered.to_string()),
+        );
+        assert!(!awaits_input(&finished));
+        assert!(!awaits_input(&registered));
+    }
+
+    fn private_message(update_id: i64, text: &str) -> Update {
+        Update {
+            update_id,
+            message: Some(ReceivedMessage {
+                message_id: update_id,
+                from: Some(User {
+                    id: 414141,
+                    is_bot: false,
+                    first_name: "Name".to_string(),
+                    username: Some("Name".to_string()),
+                    language_code: None,
+                }),
+                chat: Chat {
+                    id: 414141,
+                    type_: "private".to_string(),
+                },
+                date: 1581200384,
+                text: Some(text.to_string()),
+                caption: None,
+                document: None,
+            }),
+            callback_query: None,
+        }
+    }
+
+    fn recording_bot(updates: Vec<Update>) -> (Bot<RecordingTelegramClient>, Arc<InMemoryStore>) {
+        let store = Arc::new(InMemoryStore::new());
+        let bot = Bot::with_store(
+            RecordingTelegramClient::with_updates("expenses_bot", updates),
+            store.clone(),
+        );
+        (bot, store)
+    }
+
+    #[tokio::test]
+    async fn currency_is_chosen_on_start() {
+        let (bot, store) = recording_bot(vec![]);
+
+        for text in &["/start", "€"] {
+            let _ = bot
+                .handle_message(text.to_string(), USER_ID, USER_ID)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            bot.telegram_client.requests(),
+            vec![
+                SentRequest::Message {
+                    chat_id: USER_ID.to_string(),
+                    text: "Choose your currency".to_string(),
+                    keyboard: vec![vec!["₽".to_string(), "$".to_string(), "€".to_string()]],
+                },
+                SentRequest::Message {
+                    chat_id: USER_ID.to_string(),
+                    text: "Your currency is €".to_string(),
+                    keyboard: vec![],
+                },
+            ]
+        );
+        let user = store.get_user(USER_ID).await.unwrap();
+        assert_eq!(user.currency, Some("€".to_string()));
+    }
+
+    fn callback_query(update_id: i64, data: Option<&str>) -> Update {
+        Update {
+            update_id,
+            message: None,
+            callback_query: Some(CallbackQuery {
+                id: update_id.to_string(),
+                from: User {
+                    id: 414141,
+                    is_bot: false,
+                    first_name: "Name".to_string(),
+                    username: None,
+                    language_code: None,
+                },
+                message: Some(ReceivedMessage {
+                    message_id: 1,
+                    from: None,
+                    chat: Chat {
+                        id: 414141,
+                        type_: "private".to_string(),
+                    },
+                    date: 1581200384,
+                    text: Some("Choose your currency".to_string()),
+                    caption: None,
+                    document: None,
+                }),
+                data: data.map(str::to_string),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn keyboard_is_closed_after_choice() {
+        let (bot, store) = recording_bot(vec![]);
+
+        bot.handle_update(private_message(1, "/start"), None).await;
+        bot.handle_update(callback_query(2, Some("$")), None).await;
+
+        let requests = bot.telegram_client.requests();
+        assert_eq!(
+            requests[1..],
+            [
+                SentRequest::CallbackAnswer {
+                    callback_query_id: "2".to_string(),
+                    text: None,
+                },
+                SentRequest::Message {
+                    chat_id: USER_ID.to_string(),
+                    text: "Your currency is $".to_string(),
+                    keyboard: vec![],
+                },
+                SentRequest::EditedText {
+                    chat_id: USER_ID.to_string(),
+                    message_id: "1".to_string(),
+                    text: "Choose your currency: $".to_string(),
+                    keyboard: vec![],
+                },
+            ]
+        );
+        let user = store.get_user(USER_ID).await.unwrap();
+        assert_eq!(user.currency, Some("$".to_string()));
+    }
+
+    #[tokio::test]
+    async fn keyboard_is_closed_once_for_duplicate_press() {
+        let (bot, _) = recording_bot(vec![]);
+
+        bot.handle_update(private_message(1, "/start"), None).await;
+        bot.handle_update(callback_query(2, Some("$")), None).await;
+        bot.handle_update(callback_query(2, Some("$")), None).await;
+
+        let edits = bot
+            .telegram_client
+            .requests()
+            .into_iter()
+            .filter(|request| matches!(request, SentRequest::EditedText { .. }))
+            .count();
+        assert_eq!(edits, 1);
+    }
+
+    #[tokio::test]
+    async fn keyboard_of_another_member_is_not_closed() {
+        let (bot, _) = recording_bot(vec![]);
+        let (member, group) = ("424242", "-100");
+        for (user_id, text) in &[(USER_ID, "/start"), (USER_ID, "€")] {
+            let _ = bot
+                .handle_message(text.to_string(), user_id, user_id)
+                .await
+                .unwrap();
         }
+        for (user_id, text) in &[(member, "/start"), (member, "$")] {
+            let _ = bot
+                .handle_message(text.to_string(), user_id, user_id)
+                .await
+                .unwrap();
+        }
+        let keyboard = bot
+            .handle_message("/chart".to_string(), group, USER_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        let _ = bot
+            .handle_message("/chart".to_string(), group, member)
+            .await
+            .unwrap();
+
+        let mut press = callback_query(10, Some("By category"));
+        let query = press.callback_query.as_mut().unwrap();
+        query.from.id = member.parse().unwrap();
+        query.message = Some(keyboard);
+        bot.handle_update(press, None).await;
+
+        let requests = bot.telegram_client.requests();
+        assert_eq!(
+            requests.last().unwrap(),
+            &SentRequest::Message {
+                chat_id: group.to_string(),
+                text: "There are no records yet. Use /add to save one.".to_string(),
+                keyboard: vec![],
+            }
+        );
+        assert!(!requests
+            .iter()
+            .any(|request| matches!(request, SentRequest::EditedText { .. })));
+    }
+
+    #[tokio::test]
+    async fn every_callback_query_is_answered() {
+        let (bot, _) = recording_bot(vec![]);
+
+        bot.handle_update(callback_query(1, None), None).await;
+
+        assert_eq!(
+            bot.telegram_client.requests(),
+            vec![SentRequest::CallbackAnswer {
+                callback_query_id: "1".to_string(),
+                text: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_webhook_server_is_an_error() {
+        let (bot, _) = recording_bot(vec![]);
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = WebhookConfig {
+            url: "https://example.com/bot".to_string(),
+            address: taken.local_addr().unwrap(),
+            secret_token: "secret".to_string(),
+        };
+
+        let result = bot.init_webhook(config, future::pending()).await;
+
+        assert_eq!(result.unwrap_err().kind(), "WebhookError");
+    }
+
+    #[tokio::test]
+    async fn sent_reply_is_returned() {
+        let (bot, _) = recording_bot(vec![]);
+
+        let reply = bot
+            .handle_message("/start".to_string(), USER_ID, USER_ID)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(reply.message_id, 1);
+        assert_eq!(reply.chat.id.to_string(), USER_ID);
+        assert_eq!(reply.content(), "Choose your currency");
+    }
+
+    #[tokio::test]
+    async fn group_message_without_dialog_is_not_replied() {
+        let (bot, _) = recording_bot(vec![]);
+
+        let reply = bot
+            .handle_message("hello".to_string(), "-100", USER_ID)
+            .await
+            .unwrap();
+
+        assert_eq!(reply, None);
+        assert!(bot.telegram_client.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn disabled_commands_are_available_to_admins_only() {
+        let (bot, _) = recording_bot(vec![]);
+        let bot = bot.with_disabled_commands(vec![Command::Help], &[414141]);
+
+        let result = bot.handle_message("/help".to_string(), "1", "1").await;
+        assert_eq!(result.unwrap_err().kind(), "PermissionDenied");
+
+        let response = bot
+            .handle_message("/help".to_string(), USER_ID, USER_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.content(), HELP_TEXT);
+    }
+
+    #[tokio::test]
+    async fn unregistered_user_can_not_draw_charts() {
+        let (bot, _) = recording_bot(vec![]);
+
+        bot.handle_update(private_message(1, "/chart"), None).await;
+
+        assert_eq!(
+            bot.telegram_client.sent_texts(),
+            vec![BotError::NotRegistered.user_message(Language::English)]
+        );
+    }
+
+    #[tokio::test]
+    async fn unregistered_group_member_is_asked_to_register() {
+        let (bot, _) = recording_bot(vec![]);
+
+        for text in &["/history", "/add 90 taxi"] {
+            let result = bot.handle_message(text.to_string(), "-100", USER_ID).await;
+            assert_eq!(result.unwrap_err().kind(), "NotRegistered");
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_chat_is_not_replied() {
+        let (bot, _) = recording_bot(vec![]);
+
+        bot.report_error(USER_ID, BotError::RateLimited(5), Language::English)
+            .await;
+
+        assert!(bot.telegram_client.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unknown_chart_is_reported_without_records() {
+        let (bot, _) = recording_bot(vec![]);
+
+        for (update_id, text) in ["/start", "$", "/chart", "pie"].iter().enumerate() {
+            bot.handle_update(private_message(update_id as i64, text), None)
+                .await;
+        }
+
+        assert_eq!(
+            bot.telegram_client.sent_texts().last().unwrap(),
+            "Unknown chart: pie. Try /chart again."
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_are_explained_in_language_of_user() {
+        let (bot, _) = recording_bot(vec![]);
+        let in_russian = |mut update: Update| {
+            let from = update
+                .message
+                .as_mut()
+                .and_then(|message| message.from.as_mut());
+            from.unwrap().language_code = Some("ru".to_string());
+            update
+        };
+
+        bot.handle_update(in_russian(private_message(1, "/add")), None)
+            .await;
+        bot.handle_update(in_russian(private_message(2, "ninety")), None)
+            .await;
+
+        assert_eq!(
+            bot.telegram_client.sent_texts()[1],
+            BotError::ValidationError("ninety".to_string()).user_message(Language::Russian)
+        );
+    }
+
+    #[tokio::test]
+    async fn panic_is_caught_with_its_message() {
+        let result = catch_panic(async { panic!("dialog is broken") }).await;
+
+        assert_eq!(result, Err("dialog is broken".to_string()));
+        assert_eq!(catch_panic(async {}).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn panicking_handler_is_isolated() {
+        let store = Arc::new(InMemoryStore::new());
+        let bot = Bot::with_store(
+            RecordingTelegramClient::new("expenses_bot")
+                .with_panic_on("Write amount of money you have spent"),
+            store.clone(),
+        );
+
+        for (update_id, text) in ["/start", "€", "/add", "/help"].iter().enumerate() {
+            bot.handle_update_isolated(private_message(update_id as i64, text), None)
+                .await;
+        }
+
+        assert_eq!(
+            bot.telegram_client.sent_texts(),
+            vec![
+                "Choose your currency".to_string(),
+                "Your currency is €".to_string(),
+                BotError::Panicked(String::new()).user_message(Language::English),
+                HELP_TEXT.to_string(),
+            ]
+        );
+        let dialog = store.get_user_dialog(USER_ID, USER_ID).await.unwrap();
+        assert_eq!(dialog.command, Command::Start.to_string());
+        assert!(!awaits_input(&dialog));
+    }
+
+    #[tokio::test]
+    async fn dialog_of_unregistered_user_is_removed_after_panic() {
+        let (bot, store) = recording_bot(vec![]);
+        bot.handle_update(private_message(1, "/feedback"), None)
+            .await;
+
+        bot.reset_dialog(USER_ID, USER_ID).await;
+
+        assert!(store.get_user_dialog(USER_ID, USER_ID).await.is_err());
+        bot.handle_update(private_message(2, "thanks"), None).await;
+        assert_eq!(
+            bot.telegram_client.sent_texts().last().unwrap(),
+            &BotError::NotRegistered.user_message(Language::English)
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_update_is_not_claimed() {
+        let (bot, store) = recording_bot(vec![]);
+
+        bot.handle_update(private_message(1, "/chart"), None).await;
+        bot.handle_update(private_message(2, "/help"), None).await;
+
+        assert!(store.claim_update(1).await.unwrap());
+        assert!(!store.claim_update(2).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn interrupted_update_is_handled_after_restart() {
+        let store = Arc::new(InMemoryStore::new());
+        let bot = Bot::with_store(
+            RecordingTelegramClient::new("expenses_bot").with_panic_on(HELP_TEXT),
+            store.clone(),
+        );
+        bot.handle_update_isolated(private_message(1, "/help"), None)
+            .await;
+
+        let restarted = Bot::with_store(
+            RecordingTelegramClient::with_updates(
+                "expenses_bot",
+                vec![private_message(1, "/help")],
+            ),
+            store.clone(),
+        );
+        restarted.init_bot(future::pending()).await;
+
+        assert_eq!(
+            restarted.telegram_client.sent_texts(),
+            vec![HELP_TEXT.to_string()]
+        );
+        assert_eq!(store.next_update_id().await.unwrap(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn offset_waits_for_unfinished_updates() {
+        let (bot, store) = recording_bot(vec![]);
+        for update_id in &[5, 6] {
+            bot.updates.lock().await.receive(*update_id);
+        }
+
+        let _ = store.claim_update(6).await.unwrap();
+        bot.finish_update(6).await;
+        assert_eq!(bot.polling_offset().await, 5);
+
+        let _ = store.claim_update(5).await.unwrap();
+        bot.finish_update(5).await;
+        assert_eq!(bot.polling_offset().await, 7);
+        assert!(store.claim_update(5).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn webhook_updates_are_handled_after_shutdown() {
+        let (bot, _) = recording_bot(vec![]);
+        let (sender, receiver) = unbounded_channel();
+        let server = async move {
+            tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+            sender.send(private_message(1, "/help")).unwrap();
+        };
+
+        let _ = join(
+            bot.process_updates(receiver, None, future::ready(()), true),
+            server,
+        )
+        .await;
+
+        assert_eq!(
+            bot.telegram_client.sent_texts(),
+            vec![HELP_TEXT.to_string()]
+        );
+        assert!(bot.draining.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn polled_updates_are_processed_once() {
+        let (bot, store) = recording_bot(vec![
+            private_message(1, "/start"),
+            private_message(2, "€"),
+            private_message(3, "/add 90 taxi"),
+        ]);
+        let _ = store.claim_update(2).await.unwrap();
+
+        bot.init_bot(future::pending()).await;
+
+        let texts = bot.telegram_client.sent_texts();
+        assert_eq!(texts.len(), 2);
+        assert_eq!(texts[0], "Choose your currency");
+        assert_eq!(store.next_update_id().await.unwrap(), Some(4));
+        let user = store.get_user(USER_ID).await.unwrap();
+        assert_eq!(user.username, Some("name".to_string()));
+        assert_eq!(user.currency, None);
     }
 }
@@ -0,0 +1,40 @@
+use futures::future::{self, Either};
+use futures::pin_mut;
+use log::{error, info};
+use tokio::signal;
+
+/// completes once the process is asked to stop by SIGINT or SIGTERM
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = signal::ctrl_c().await {
+            error!("failed to listen for SIGINT: {}", err);
+            future::pending::<()>().await;
+        }
+    };
+    let terminate = terminate_signal();
+    pin_mut!(interrupt, terminate);
+
+    match future::select(interrupt, terminate).await {
+        Either::Left(_) => info!("received SIGINT"),
+        Either::Right(_) => info!("received SIGTERM"),
+    }
+}
+
+#[cfg(unix)]
+async fn terminate_signal() {
+    match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            let _ = terminate.recv().await;
+        }
+        Err(err) => {
+            error!("failed to listen for SIGTERM: {}", err);
+            future::pending::<()>().await;
+        }
+    }
+}
+
+/// orchestrators stop processes by SIGTERM on unix only
+#[cfg(not(unix))]
+async fn terminate_signal() {
+    future::pending::<()>().await;
+}
@@ -0,0 +1,420 @@
+use std::str::FromStr;
+
+pub const SPLIT_USAGE: &str =
+    "Use /add <amount> <category> split @member ... to split an expense. \
+Write @member:2 to split by shares or @member=30 to set exact amounts.";
+
+/// Amounts which differ by less than a cent are considered equal.
+const CENT: f32 = 0.01;
+
+/// How an expense is split between the member who paid it and mentioned members.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Split {
+    /// amount is split equally between the payer and mentioned members
+    Equal(Vec<String>),
+    /// amount is split in proportion to shares, the payer has a share of 1 unless mentioned
+    Shares(Vec<(String, u32)>),
+    /// mentioned members owe exact amounts, the rest of the amount is the payer's part
+    Exact(Vec<(String, f32)>),
+}
+
+/// Expense sent along with /add command, e.g. `/add 90 dinner split @alice @bob`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expense {
+    pub amount: f32,
+    pub category: Option<String>,
+    pub split: Option<Split>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub amount: f32,
+}
+
+impl FromStr for Expense {
+    type Err = String;
+
+    fn from_str(arguments: &str) -> Result<Self, Self::Err> {
+        let mut words = arguments.split_whitespace();
+        let amount = words.next().unwrap_or("");
+        let amount = match f32::from_str(amount) {
+            Ok(amount) if amount.is_finite() && amount > 0.0 => amount,
+            _ => return Err(format!("Can not parse: {} to number.", amount)),
+        };
+
+        let mut category = vec![];
+        let mut members = vec![];
+        let mut is_split = false;
+        for word in words {
+            if is_split {
+                members.push(word);
+            } else if word.eq_ignore_ascii_case("split") {
+                is_split = true;
+            } else {
+                category.push(word);
+            }
+        }
+
+        Ok(Expense {
+            amount,
+            category: if category.is_empty() {
+                None
+            } else {
+                Some(category.join(" "))
+            },
+            split: if is_split {
+                Some(parse_split(&members)?)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+fn parse_split(mentions: &[&str]) -> Result<Split, String> {
+    if mentions.is_empty() {
+        return Err("Mention members to split the expense with.".to_string());
+    }
+
+    let mut equal = vec![];
+    let mut shares = vec![];
+    let mut exact = vec![];
+    for mention in mentions {
+        let member = match mention.strip_prefix('@') {
+            Some(member) if !member.is_empty() => member,
+            _ => return Err(format!("{} is not a mention of a member.", mention)),
+        };
+        if let Some(index) = member.find(':') {
+            match u32::from_str(&member[index + 1..]) {
+                Ok(share) if share > 0 => shares.push((member[..index].to_lowercase(), share)),
+                _ => return Err(format!("Can not parse share of {}.", mention)),
+            }
+        } else if let Some(index) = member.find('=') {
+            let name = member[..index].to_lowercase();
+            // every member owes a single amount, a split is kept once per member
+            if exact.iter().any(|(known, _)| *known == name) {
+                return Err(format!("Amount of @{} is set more than once.", name));
+            }
+            match f32::from_str(&member[index + 1..]) {
+                Ok(amount) if amount.is_finite() && amount >= 0.0 => exact.push((name, amount)),
+                _ => return Err(format!("Can not parse amount of {}.", mention)),
+            }
+        } else {
+            equal.push(member.to_lowercase());
+        }
+    }
+
+    match (equal.is_empty(), shares.is_empty(), exact.is_empty()) {
+        (false, true, true) => Ok(Split::Equal(equal)),
+        (true, false, true) => Ok(Split::Shares(shares)),
+        (true, true, false) => Ok(Split::Exact(exact)),
+        _ => Err("Do not mix equal split, shares and exact amounts.".to_string()),
+    }
+}
+
+impl Split {
+    /// members mentioned in the split
+    pub fn members(&self) -> Vec<&str> {
+        match self {
+            Split::Equal(members) => members.iter().map(String::as_str).collect(),
+            Split::Shares(shares) => shares.iter().map(|(member, _)| member.as_str()).collect(),
+            Split::Exact(exact) => exact.iter().map(|(member, _)| member.as_str()).collect(),
+        }
+    }
+
+    /// same split with members replaced, e.g. usernames replaced with user ids
+    pub fn map_members<F>(&self, f: F) -> Split
+    where
+        F: Fn(&str) -> String,
+    {
+        match self {
+            Split::Equal(members) => {
+                Split::Equal(members.iter().map(|member| f(member.as_str())).collect())
+            }
+            Split::Shares(shares) => Split::Shares(
+                shares
+                    .iter()
+                    .map(|(member, share)| (f(member.as_str()), *share))
+                    .collect(),
+            ),
+            Split::Exact(exact) => Split::Exact(
+                exact
+                    .iter()
+                    .map(|(member, amount)| (f(member.as_str()), *amount))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// parts of the amount owed by each participant of the split including the payer
+    pub fn amounts(&self, amount: f32, payer: &str) -> Result<Vec<(String, f32)>, String> {
+        match self {
+            Split::Equal(members) => {
+                let mut participants = vec![payer.to_string()];
+                for member in members {
+                    if !participants.contains(member) {
+                        participants.push(member.clone());
+                    }
+                }
+                let part = amount / participants.len() as f32;
+                Ok(participants
+                    .into_iter()
+                    .map(|participant| (participant, part))
+                    .collect())
+            }
+            Split::Shares(shares) => {
+                let mut weights: Vec<(String, u32)> = vec![];
+                if !shares.iter().any(|(member, _)| member == payer) {
+                    weights.push((payer.to_string(), 1));
+                }
+                for (member, share) in shares {
+                    match weights.iter_mut().find(|(known, _)| known == member) {
+                        Some((_, weight)) => *weight += share,
+                        None => weights.push((member.clone(), *share)),
+                    }
+                }
+                let total = weights.iter().map(|(_, weight)| weight).sum::<u32>() as f32;
+                Ok(weights
+                    .into_iter()
+                    .map(|(member, weight)| (member, amount * weight as f32 / total))
+                    .collect())
+            }
+            Split::Exact(exact) => {
+                let listed = exact.iter().map(|(_, part)| part).sum::<f32>();
+                if listed > amount + CENT {
+                    return Err(format!(
+                        "Split amounts {} exceed the expense {}.",
+                        listed, amount
+                    ));
+                }
+                let mut amounts = exact.clone();
+                if amounts.iter().any(|(member, _)| member == payer) {
+                    if amount - listed > CENT {
+                        return Err(format!(
+                            "Split amounts {} do not add up to the expense {}.",
+                            listed, amount
+                        ));
+                    }
+                } else if amount - listed > CENT {
+                    amounts.insert(0, (payer.to_string(), amount - listed));
+                }
+                Ok(amounts)
+            }
+        }
+    }
+}
+
+/// net balance of each member, positive when others owe the member.
+/// Debts are (paid by, owed by, amount).
+pub fn balances(debts: &[(String, String, f32)]) -> Vec<(String, f32)> {
+    let mut balances: Vec<(String, f32)> = vec![];
+    for (payer, debtor, amount) in debts {
+        for (member, change) in [(payer, *amount), (debtor, -*amount)] {
+            match balances.iter_mut().find(|(known, _)| known == member) {
+                Some((_, balance)) => *balance += change,
+                None => balances.push((member.clone(), change)),
+            }
+        }
+    }
+    balances
+}
+
+/// transfers which settle up the balances. The largest debtor pays the largest creditor
+/// until everybody is settled, so there are at most n - 1 transfers for n members.
+pub fn settle_up(balances: &[(String, f32)]) -> Vec<Transfer> {
+    let to_cents = |amount: f32| (amount * 100.0).round() as i64;
+    let mut creditors: Vec<(&str, i64)> = balances
+        .iter()
+        .map(|(member, balance)| (member.as_str(), to_cents(*balance)))
+        .filter(|(_, cents)| *cents > 0)
+        .collect();
+    let mut debtors: Vec<(&str, i64)> = balances
+        .iter()
+        .map(|(member, balance)| (member.as_str(), -to_cents(*balance)))
+        .filter(|(_, cents)| *cents > 0)
+        .collect();
+    creditors.sort_by(|a, b| b.1.cmp(&a.1));
+    debtors.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut transfers = vec![];
+    let (mut creditor, mut debtor) = (0, 0);
+    while creditor < creditors.len() && debtor < debtors.len() {
+        let cents = creditors[creditor].1.min(debtors[debtor].1);
+        transfers.push(Transfer {
+            from: debtors[debtor].0.to_string(),
+            to: creditors[creditor].0.to_string(),
+            amount: cents as f32 / 100.0,
+        });
+        creditors[creditor].1 -= cents;
+        debtors[debtor].1 -= cents;
+        if creditors[creditor].1 == 0 {
+            creditor += 1;
+        }
+        if debtors[debtor].1 == 0 {
+            debtor += 1;
+        }
+    }
+    transfers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debt(payer: &str, debtor: &str, amount: f32) -> (String, String, f32) {
+        (payer.to_string(), debtor.to_string(), amount)
+    }
+
+    #[test]
+    fn parse_expense_without_split() {
+        assert_eq!(
+            "90 dinner at home".parse::<Expense>(),
+            Ok(Expense {
+                amount: 90.0,
+                category: Some("dinner at home".to_string()),
+                split: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_expense_with_equal_split() {
+        assert_eq!(
+            "90 dinner split @Alice @bob".parse::<Expense>(),
+            Ok(Expense {
+                amount: 90.0,
+                category: Some("dinner".to_string()),
+                split: Some(Split::Equal(vec!["alice".to_string(), "bob".to_string()])),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_expense_with_shares_and_exact_amounts() {
+        assert_eq!(
+            "90 split @alice:2 @bob:1".parse::<Expense>().unwrap().split,
+            Some(Split::Shares(vec![
+                ("alice".to_string(), 2),
+                ("bob".to_string(), 1)
+            ]))
+        );
+        assert_eq!(
+            "90 split @alice=30".parse::<Expense>().unwrap().split,
+            Some(Split::Exact(vec![("alice".to_string(), 30.0)]))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_expense() {
+        assert!("dinner".parse::<Expense>().is_err());
+        assert!("-5 dinner".parse::<Expense>().is_err());
+        assert!("90 dinner split".parse::<Expense>().is_err());
+        assert!("90 dinner split alice".parse::<Expense>().is_err());
+        assert!("90 dinner split @alice @bob:2".parse::<Expense>().is_err());
+        assert!("90 dinner split @alice:0".parse::<Expense>().is_err());
+        assert!("inf dinner".parse::<Expense>().is_err());
+        assert!("90 dinner split @alice=inf".parse::<Expense>().is_err());
+    }
+
+    #[test]
+    fn exact_amount_of_member_is_set_once() {
+        assert_eq!(
+            "90 split @alice=10 @Alice=20".parse::<Expense>(),
+            Err("Amount of @alice is set more than once.".to_string())
+        );
+    }
+
+    #[test]
+    fn equal_split_includes_payer() {
+        let split = Split::Equal(vec!["alice".to_string(), "bob".to_string()]);
+        assert_eq!(
+            split.amounts(90.0, "me"),
+            Ok(vec![
+                ("me".to_string(), 30.0),
+                ("alice".to_string(), 30.0),
+                ("bob".to_string(), 30.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn shares_split_gives_payer_one_share() {
+        let split = Split::Shares(vec![("alice".to_string(), 2)]);
+        assert_eq!(
+            split.amounts(90.0, "me"),
+            Ok(vec![("me".to_string(), 30.0), ("alice".to_string(), 60.0)])
+        );
+    }
+
+    #[test]
+    fn exact_split_leaves_rest_to_payer() {
+        let split = Split::Exact(vec![("alice".to_string(), 60.0)]);
+        assert_eq!(
+            split.amounts(90.0, "me"),
+            Ok(vec![("me".to_string(), 30.0), ("alice".to_string(), 60.0)])
+        );
+        let split = Split::Exact(vec![("alice".to_string(), 100.0)]);
+        assert!(split.amounts(90.0, "me").is_err());
+        let split = Split::Exact(vec![("me".to_string(), 10.0), ("alice".to_string(), 10.0)]);
+        assert!(split.amounts(90.0, "me").is_err());
+    }
+
+    #[test]
+    fn map_members_replaces_usernames() {
+        let split = Split::Shares(vec![("alice".to_string(), 2)]);
+        assert_eq!(
+            split.map_members(|member| format!("id_of_{}", member)),
+            Split::Shares(vec![("id_of_alice".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn balances_are_netted() {
+        let debts = vec![
+            debt("me", "alice", 30.0),
+            debt("alice", "me", 10.0),
+            debt("me", "bob", 30.0),
+        ];
+        assert_eq!(
+            balances(&debts),
+            vec![
+                ("me".to_string(), 50.0),
+                ("alice".to_string(), -20.0),
+                ("bob".to_string(), -30.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn settle_up_with_minimal_transfers() {
+        let balances = vec![
+            ("me".to_string(), 50.0),
+            ("alice".to_string(), -20.0),
+            ("bob".to_string(), -30.0),
+            ("carol".to_string(), 0.0),
+        ];
+        assert_eq!(
+            settle_up(&balances),
+            vec![
+                Transfer {
+                    from: "bob".to_string(),
+                    to: "me".to_string(),
+                    amount: 30.0
+                },
+                Transfer {
+                    from: "alice".to_string(),
+                    to: "me".to_string(),
+                    amount: 20.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_to_settle_up() {
+        let balances = vec![("me".to_string(), 0.0), ("alice".to_string(), 0.001)];
+        assert!(settle_up(&balances).is_empty());
+    }
+}
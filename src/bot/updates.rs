@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+
+/// Keeps track of updates being handled, so polling resumes from the oldest unfinished one.
+/// Workers finish updates out of order, e.g. update 6 of one user may be finished before
+/// update 5 of another, so the offset moves only past updates which have all been finished.
+#[derive(Debug, Default)]
+pub struct UpdateTracker {
+    /// received updates which are not finished yet
+    pending: BTreeSet<i64>,
+    /// id following the latest received update
+    next_received: Option<i64>,
+    /// offset which has been returned to be kept the last time
+    confirmed: Option<i64>,
+}
+
+impl UpdateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn receive(&mut self, update_id: i64) {
+        let _ = self.pending.insert(update_id);
+        self.next_received = self.next_received.max(Some(update_id + 1));
+    }
+
+    /// marks the update as finished, returns the new offset to poll from if it has moved
+    pub fn finish(&mut self, update_id: i64) -> Option<i64> {
+        let _ = self.pending.remove(&update_id);
+        let offset = self.pending.iter().next().cloned().or(self.next_received)?;
+        if Some(offset) <= self.confirmed {
+            return None;
+        }
+        self.confirmed = Some(offset);
+        Some(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_waits_for_older_updates() {
+        let mut tracker = UpdateTracker::new();
+        tracker.receive(5);
+        tracker.receive(6);
+
+        assert_eq!(tracker.finish(6), Some(5));
+        assert_eq!(tracker.finish(5), Some(7));
+    }
+
+    #[test]
+    fn offset_does_not_move_back() {
+        let mut tracker = UpdateTracker::new();
+        tracker.receive(5);
+        assert_eq!(tracker.finish(5), Some(6));
+
+        tracker.receive(6);
+        tracker.receive(7);
+        assert_eq!(tracker.finish(7), None);
+        assert_eq!(tracker.finish(6), Some(8));
+    }
+}
@@ -0,0 +1,628 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use crate::bot::dialogs::Command;
+use crate::db::DEFAULT_POOL_SIZE;
+use crate::log::logger::{LogConfig, LogFormat};
+use crate::telegram::client::{ProxyConfig, DEFAULT_API_URL};
+use crate::telegram::webhook::WebhookConfig;
+
+const DEFAULT_WEBHOOK_ADDRESS: &str = "0.0.0.0:8443";
+
+/// Config file read when `CONFIG_FILE` is not set, it may be missing.
+const DEFAULT_CONFIG_FILE: &str = "bot.toml";
+
+/// Settings of the bot, read from a TOML file and env variables which take precedence over it.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub telegram_token: String,
+    /// Bot API server, e.g. a self-hosted one
+    pub telegram_api_url: String,
+    /// requests to Telegram go directly when it is not set
+    pub proxy: Option<ProxyConfig>,
+    pub database_url: String,
+    /// maximum number of connections to the database
+    pub pool_size: u32,
+    /// updates are received via webhook when it is configured, via long polling otherwise
+    pub webhook: Option<WebhookConfig>,
+    /// address of health probes and metrics, they are not served when it is not set
+    pub health_address: Option<SocketAddr>,
+    /// Telegram ids of users who may use disabled commands
+    pub admin_ids: Vec<i64>,
+    /// commands turned off for everyone but admins, e.g. until they are ready
+    pub disabled_commands: Vec<Command>,
+    /// periodic jobs are not run when it is not set
+    pub scheduler: Option<SchedulerConfig>,
+    pub log: LogConfig,
+}
+
+/// Settings of periodic jobs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulerConfig {
+    /// time between runs of the jobs
+    pub interval: Duration,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, io::Error),
+    Parse(String, toml::de::Error),
+    Invalid(Vec<String>),
+}
+
+impl Error for ConfigError {}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => write!(f, "can not read config {}: {}", path, err),
+            ConfigError::Parse(path, err) => write!(f, "invalid config {}: {}", path, err),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration: {}", problems.join("; "))
+            }
+        }
+    }
+}
+
+/// Content of the config file, every setting is optional there as it may come from env.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    #[serde(default)]
+    telegram: TelegramSettings,
+    #[serde(default)]
+    database: DatabaseSettings,
+    #[serde(default)]
+    health: HealthSettings,
+    #[serde(default)]
+    bot: BotSettings,
+    #[serde(default)]
+    scheduler: SchedulerSettings,
+    #[serde(default)]
+    log: LogSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TelegramSettings {
+    token: Option<String>,
+    api_url: Option<String>,
+    #[serde(default)]
+    webhook: WebhookSettings,
+    #[serde(default)]
+    proxy: ProxySettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProxySettings {
+    url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebhookSettings {
+    url: Option<String>,
+    secret_token: Option<String>,
+    address: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatabaseSettings {
+    url: Option<String>,
+    pool_size: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HealthSettings {
+    address: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BotSettings {
+    admin_ids: Option<Vec<i64>>,
+    disabled_commands: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchedulerSettings {
+    interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogSettings {
+    filter: Option<String>,
+    format: Option<String>,
+    sensitive_data: Option<bool>,
+    redaction_salt: Option<String>,
+}
+
+impl Config {
+    /// config with defaults for everything but the token and the database, e.g. for tests
+    pub fn new(telegram_token: &str, database_url: &str) -> Self {
+        Config {
+            telegram_token: telegram_token.to_string(),
+            telegram_api_url: DEFAULT_API_URL.to_string(),
+            proxy: None,
+            database_url: database_url.to_string(),
+            pool_size: DEFAULT_POOL_SIZE,
+            webhook: None,
+            health_address: None,
+            admin_ids: vec![],
+            disabled_commands: vec![],
+            scheduler: None,
+            log: LogConfig::default(),
+        }
+    }
+
+    /// reads the file set by `CONFIG_FILE`, `bot.toml` by default, and overrides its settings
+    /// by env variables. The default file may be missing, the one set explicitly has to exist.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        match env::var("CONFIG_FILE") {
+            Ok(path) => Self::read(Path::new(&path), true),
+            Err(_) => Self::read(Path::new(DEFAULT_CONFIG_FILE), false),
+        }
+    }
+
+    /// reads the config file if it exists and overrides its settings by env variables.
+    /// All invalid and missing settings are reported at once.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        Self::read(path, false)
+    }
+
+    fn read(path: &Path, required: bool) -> Result<Self, ConfigError> {
+        let settings = read_settings(path, required)?;
+        Self::from_settings(settings, |name| env::var(name).ok())
+    }
+
+    fn from_settings<F>(settings: Settings, var: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut problems = vec![];
+
+        let telegram_token = var("TELEGRAM_BOT_TOKEN").or(settings.telegram.token);
+        let telegram_api_url = var("TELEGRAM_API_URL")
+            .or(settings.telegram.api_url)
+            .unwrap_or_else(|| DEFAULT_API_URL.to_string());
+        let database_url = var("DATABASE_URL").or(settings.database.url);
+        let pool_size = match var("DATABASE_POOL_SIZE") {
+            Some(pool_size) => pool_size.parse().ok().or_else(|| {
+                problems.push(format!("DATABASE_POOL_SIZE is not a number: {}", pool_size));
+                None
+            }),
+            None => settings.database.pool_size,
+        }
+        .unwrap_or(DEFAULT_POOL_SIZE);
+        let health_address = parse_address(
+            "health address",
+            var("HEALTH_ADDRESS").or(settings.health.address),
+            &mut problems,
+        );
+
+        let webhook = settings.telegram.webhook;
+        let webhook_url = var("TELEGRAM_WEBHOOK_URL").or(webhook.url);
+        let webhook_secret = var("TELEGRAM_WEBHOOK_SECRET").or(webhook.secret_token);
+        let webhook_address = parse_address(
+            "webhook address",
+            Some(
+                var("TELEGRAM_WEBHOOK_ADDRESS")
+                    .or(webhook.address)
+                    .unwrap_or_else(|| DEFAULT_WEBHOOK_ADDRESS.to_string()),
+            ),
+            &mut problems,
+        );
+
+        let proxy = settings.telegram.proxy;
+        let proxy_url = var("TELEGRAM_PROXY_URL").or(proxy.url);
+        let proxy_username = var("TELEGRAM_PROXY_USERNAME").or(proxy.username);
+        let proxy_password = var("TELEGRAM_PROXY_PASSWORD").or(proxy.password);
+
+        let admin_ids = match var("ADMIN_IDS") {
+            Some(ids) => split_list(&ids)
+                .filter_map(|id| match id.parse() {
+                    Ok(id) => Some(id),
+                    Err(_) => {
+                        problems.push(format!("admin id is not a number: {}", id));
+                        None
+                    }
+                })
+                .collect(),
+            None => settings.bot.admin_ids.unwrap_or_default(),
+        };
+        let disabled_commands = match var("DISABLED_COMMANDS") {
+            Some(commands) => split_list(&commands).map(str::to_string).collect(),
+            None => settings.bot.disabled_commands.unwrap_or_default(),
+        }
+        .iter()
+        .filter_map(|command| match Command::from_str(command) {
+            Ok(command) => Some(command),
+            Err(_) => {
+                problems.push(format!("unknown disabled command: {}", command));
+                None
+            }
+        })
+        .collect();
+        let scheduler = match var("SCHEDULER_INTERVAL_SECS") {
+            Some(interval) => interval.parse().ok().or_else(|| {
+                problems.push(format!(
+                    "SCHEDULER_INTERVAL_SECS is not a number: {}",
+                    interval
+                ));
+                None
+            }),
+            None => settings.scheduler.interval_secs,
+        }
+        .and_then(|interval_secs| {
+            if interval_secs == 0 {
+                problems.push("scheduler interval has to be positive".to_string());
+                return None;
+            }
+            Some(SchedulerConfig {
+                interval: Duration::from_secs(interval_secs),
+            })
+        });
+
+        let log_settings = settings.log;
+        let log_filter = var("RUST_LOG")
+            .or(log_settings.filter)
+            .unwrap_or_else(|| LogConfig::default().filter);
+        if let Err(err) = EnvFilter::try_new(&log_filter) {
+            problems.push(format!("log filter is invalid: {}", err));
+        }
+        let log_format = match var("LOG_FORMAT").or(log_settings.format).as_deref() {
+            None | Some("text") => LogFormat::Text,
+            Some("json") => LogFormat::Json,
+            Some(format) => {
+                problems.push(format!("log format has to be text or json: {}", format));
+                LogFormat::Text
+            }
+        };
+        let sensitive_data = match var("LOG_SENSITIVE_DATA").as_deref() {
+            Some("true") => true,
+            Some("false") => false,
+            Some(value) => {
+                problems.push(format!(
+                    "LOG_SENSITIVE_DATA has to be true or false: {}",
+                    value
+                ));
+                false
+            }
+            None => log_settings.sensitive_data.unwrap_or(false),
+        };
+        let log = LogConfig {
+            filter: log_filter,
+            format: log_format,
+            sensitive_data,
+            redaction_salt: var("LOG_REDACTION_SALT")
+                .or(log_settings.redaction_salt)
+                .filter(|salt| !salt.is_empty()),
+        };
+
+        let telegram_token = required("telegram token", telegram_token, &mut problems);
+        if !telegram_api_url.starts_with("https://") && !telegram_api_url.starts_with("http://") {
+            problems.push(format!(
+                "telegram api url has to use http or https: {}",
+                telegram_api_url
+            ));
+        }
+        let telegram_api_url = telegram_api_url.trim_end_matches('/').to_string();
+        let proxy = match proxy_url {
+            Some(url) => {
+                let schemes = ["http://", "https://", "socks5://", "socks5h://"];
+                if !schemes.iter().any(|scheme| url.starts_with(scheme)) {
+                    problems.push(format!(
+                        "proxy url has to use http, https, socks5 or socks5h: {}",
+                        url
+                    ));
+                }
+                if proxy_password.is_some() && proxy_username.is_none() {
+                    problems.push("proxy password is set without a username".to_string());
+                }
+                Some(ProxyConfig {
+                    url,
+                    username: proxy_username,
+                    password: proxy_password,
+                })
+            }
+            None => None,
+        };
+        let database_url = required("database url", database_url, &mut problems);
+        if pool_size == 0 {
+            problems.push("database pool size has to be positive".to_string());
+        }
+        let webhook = match webhook_url {
+            Some(url) => {
+                if !url.starts_with("https://") {
+                    problems.push(format!("webhook url has to use https: {}", url));
+                }
+                let secret_token = required("webhook secret token", webhook_secret, &mut problems);
+                // Telegram accepts only these characters in the header with the secret token
+                let is_valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+                if !secret_token.chars().all(is_valid) || secret_token.len() > 256 {
+                    problems.push(
+                        "webhook secret token has to be up to 256 characters A-Z, a-z, 0-9, _ or -"
+                            .to_string(),
+                    );
+                }
+                webhook_address.map(|address| WebhookConfig {
+                    url,
+                    address,
+                    secret_token,
+                })
+            }
+            None => None,
+        };
+
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+        Ok(Config {
+            telegram_token,
+            telegram_api_url,
+            proxy,
+            database_url,
+            pool_size,
+            webhook,
+            health_address,
+            admin_ids,
+            disabled_commands,
+            scheduler,
+            log,
+        })
+    }
+}
+
+fn read_settings(path: &Path, required: bool) -> Result<Settings, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(content) => toml::from_str(&content)
+            .map_err(|err| ConfigError::Parse(path.display().to_string(), err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !required => Ok(Settings::default()),
+        Err(err) => Err(ConfigError::Read(path.display().to_string(), err)),
+    }
+}
+
+/// items of a comma separated list in an env variable, e.g. `ADMIN_IDS=1,2`
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn required(name: &str, value: Option<String>, problems: &mut Vec<String>) -> String {
+    match value {
+        Some(value) if !value.trim().is_empty() => value,
+        _ => {
+            problems.push(format!("{} is missing", name));
+            String::new()
+        }
+    }
+}
+
+fn parse_address(
+    name: &str,
+    address: Option<String>,
+    problems: &mut Vec<String>,
+) -> Option<SocketAddr> {
+    match address?.parse() {
+        Ok(address) => Some(address),
+        Err(err) => {
+            problems.push(format!("{} is invalid: {}", name, err));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn load(file: &str, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        Config::from_settings(toml::from_str(file).unwrap(), |name| {
+            vars.get(name).cloned()
+        })
+    }
+
+    #[test]
+    fn env_variables_override_config_file() {
+        let file = r#"
+            [telegram]
+            token = "file-token"
+
+            [telegram.webhook]
+            url = "https://bot.example.com"
+            secret_token = "secret"
+
+            [database]
+            url = "postgres://localhost/bot"
+            pool_size = 4
+        "#;
+
+        let config = load(file, &[("TELEGRAM_BOT_TOKEN", "env-token")]).unwrap();
+
+        assert_eq!(config.telegram_token, "env-token");
+        assert_eq!(config.database_url, "postgres://localhost/bot");
+        assert_eq!(config.pool_size, 4);
+        assert_eq!(config.health_address, None);
+        let webhook = config.webhook.unwrap();
+        assert_eq!(webhook.url, "https://bot.example.com");
+        assert_eq!(webhook.address, DEFAULT_WEBHOOK_ADDRESS.parse().unwrap());
+    }
+
+    #[test]
+    fn all_problems_are_reported() {
+        let vars = [
+            ("DATABASE_POOL_SIZE", "many"),
+            ("TELEGRAM_WEBHOOK_URL", "http://bot.example.com"),
+            ("HEALTH_ADDRESS", "localhost"),
+        ];
+
+        let problems = match load("", &vars) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            result => panic!("unexpected result: {:?}", result),
+        };
+
+        assert_eq!(problems.len(), 6);
+        assert!(problems.contains(&"telegram token is missing".to_string()));
+        assert!(problems.contains(&"database url is missing".to_string()));
+        assert!(problems.contains(&"webhook secret token is missing".to_string()));
+    }
+
+    #[test]
+    fn proxy_and_api_url_are_configured() {
+        let file = r#"
+            [telegram]
+            token = "token"
+            api_url = "http://localhost:8081/"
+
+            [telegram.proxy]
+            url = "socks5://proxy.example.com:1080"
+            username = "bot"
+
+            [database]
+            url = "postgres://localhost/bot"
+        "#;
+
+        let config = load(file, &[("TELEGRAM_PROXY_PASSWORD", "secret")]).unwrap();
+
+        assert_eq!(config.telegram_api_url, "http://localhost:8081");
+        assert_eq!(
+            config.proxy,
+            Some(ProxyConfig {
+                url: "socks5://proxy.example.com:1080".to_string(),
+                username: Some("bot".to_string()),
+                password: Some("secret".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_proxy_is_reported() {
+        let vars = [
+            ("TELEGRAM_BOT_TOKEN", "token"),
+            ("DATABASE_URL", "postgres://localhost/bot"),
+            ("TELEGRAM_PROXY_URL", "ftp://proxy.example.com"),
+            ("TELEGRAM_PROXY_PASSWORD", "secret"),
+        ];
+
+        let problems = match load("", &vars) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            result => panic!("unexpected result: {:?}", result),
+        };
+
+        assert_eq!(problems.len(), 2);
+    }
+
+    #[test]
+    fn bot_scheduler_and_log_settings_are_read() {
+        let file = r#"
+            [telegram]
+            token = "token"
+
+            [database]
+            url = "postgres://localhost/bot"
+
+            [bot]
+            admin_ids = [414141]
+            disabled_commands = ["/chart"]
+
+            [scheduler]
+            interval_secs = 3600
+
+            [log]
+            filter = "info,bot::db=debug"
+            format = "json"
+            redaction_salt = "file-salt"
+        "#;
+
+        let config = load(
+            file,
+            &[
+                ("DISABLED_COMMANDS", "/chart, /settle"),
+                ("LOG_SENSITIVE_DATA", "true"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.admin_ids, vec![414141]);
+        assert_eq!(
+            config.disabled_commands,
+            vec![Command::Chart, Command::Settle]
+        );
+        assert_eq!(
+            config.scheduler,
+            Some(SchedulerConfig {
+                interval: Duration::from_secs(3600)
+            })
+        );
+        assert_eq!(
+            config.log,
+            LogConfig {
+                filter: "info,bot::db=debug".to_string(),
+                format: LogFormat::Json,
+                sensitive_data: true,
+                redaction_salt: Some("file-salt".to_string()),
+            }
+        );
+        assert!(!format!("{:?}", config.log).contains("file-salt"));
+    }
+
+    #[test]
+    fn invalid_bot_and_log_settings_are_reported() {
+        let vars = [
+            ("TELEGRAM_BOT_TOKEN", "token"),
+            ("DATABASE_URL", "postgres://localhost/bot"),
+            ("ADMIN_IDS", "414141,admin"),
+            ("DISABLED_COMMANDS", "/charts"),
+            ("SCHEDULER_INTERVAL_SECS", "0"),
+            ("LOG_FORMAT", "xml"),
+            ("LOG_SENSITIVE_DATA", "yes"),
+        ];
+
+        let problems = match load("", &vars) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            result => panic!("unexpected result: {:?}", result),
+        };
+
+        assert_eq!(problems.len(), 5);
+    }
+
+    #[test]
+    fn explicitly_set_config_file_has_to_exist() {
+        let path = Path::new("missing-bot.toml");
+
+        assert!(read_settings(path, false).is_ok());
+        assert!(matches!(
+            read_settings(path, true),
+            Err(ConfigError::Read(_, _))
+        ));
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(toml::from_str::<Settings>("[telegram]\ntokn = \"token\"").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use async_trait::async_trait;
+use diesel::result::Error as QueryError;
+use diesel::{sql_query, RunQueryDsl};
+use tokio::task::spawn_blocking;
+
+use crate::db::error::DbError;
+use crate::db::models::dialog::DialogEntity;
+use crate::db::models::history::{
+    HistoryEntity, HistoryPatch, HistoryRepository, NewHistoryRecord,
+};
+use crate::db::models::ledger::LedgerEntity;
+use crate::db::models::split::SplitRepository;
+use crate::db::models::update::UpdateRepository;
+use crate::db::models::user::UserEntity;
+use crate::db::store::{
+    DialogStore, HealthStore, HistoryStore, LedgerStore, UpdateStore, UserStore,
+};
+use crate::db::{Connection, DbConnectionPool};
+
+/// Postgres backed store. Diesel queries are blocking, so they are run on the
+/// blocking thread pool instead of the threads of the async runtime.
+#[derive(Debug, Clone)]
+pub struct Database {
+    pool: DbConnectionPool,
+}
+
+impl Database {
+    pub fn new(pool: DbConnectionPool) -> Self {
+        Database { pool }
+    }
+
+    /// runs the query with a connection from the pool on the blocking thread pool
+    pub async fn run<F, T>(&self, query: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&Connection) -> Result<T, QueryError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        spawn_blocking(move || {
+            let conn = pool.establish_connection()?;
+            Ok(query(&conn)?)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl UserStore for Database {
+    async fn get_user(&self, user_id: &str) -> Result<UserEntity, DbError> {
+        let user_id = user_id.to_string();
+        self.run(move |conn| UserEntity::get_user(&user_id, conn))
+            .await
+    }
+
+    async fn get_users_by_ids(&self, user_ids: Vec<String>) -> Result<Vec<UserEntity>, DbError> {
+        self.run(move |conn| UserEntity::get_users_by_ids(&user_ids, conn))
+            .await
+    }
+
+    async fn find_ledger_member(
+        &self,
+        ledger_id: &str,
+        username: &str,
+    ) -> Result<UserEntity, DbError> {
+        let (ledger_id, username) = (ledger_id.to_string(), username.to_string());
+        self.run(move |conn| UserEntity::find_ledger_member(&ledger_id, &username, conn))
+            .await
+    }
+
+    async fn save_user(&self, user_id: &str) -> Result<UserEntity, DbError> {
+        let user_id = user_id.to_string();
+        self.run(move |conn| UserEntity::save_user(&user_id, conn))
+            .await
+    }
+
+    async fn is_registered(&self, user_id: &str) -> Result<bool, DbError> {
+        let user_id = user_id.to_string();
+        self.run(move |conn| UserEntity::is_registered(&user_id, conn))
+            .await
+    }
+
+    async fn update_currency(&self, currency: &str, user_id: &str) -> Result<usize, DbError> {
+        let (currency, user_id) = (currency.to_string(), user_id.to_string());
+        self.run(move |conn| UserEntity::update_currency(&currency, &user_id, conn))
+            .await
+    }
+
+    async fn update_ledger(
+        &self,
+        ledger_id: Option<&str>,
+        user_id: &str,
+    ) -> Result<usize, DbError> {
+        let (ledger_id, user_id) = (ledger_id.map(str::to_string), user_id.to_string());
+        self.run(move |conn| UserEntity::update_ledger(ledger_id.as_deref(), &user_id, conn))
+            .await
+    }
+
+    async fn update_names(
+        &self,
+        username: Option<&str>,
+        first_name: &str,
+        user_id: &str,
+    ) -> Result<usize, DbError> {
+        let username = username.map(str::to_string);
+        let (first_name, user_id) = (first_name.to_string(), user_id.to_string());
+        self.run(move |conn| {
+            UserEntity::update_names(username.as_deref(), &first_name, &user_id, conn)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DialogStore for Database {
+    async fn get_user_dialog(&self, chat_id: &str, user_id: &str) -> Result<DialogEntity, DbError> {
+        let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+        self.run(move |conn| DialogEntity::get_user_dialog(&chat_id, &user_id, conn))
+            .await
+    }
+
+    async fn save_dialog(&self, dialog: DialogEntity) -> Result<(), DbError> {
+        self.run(move |conn| DialogEntity::save_dialog(&dialog, conn))
+            .await
+    }
+
+    async fn delete_dialog(&self, chat_id: &str, user_id: &str) -> Result<(), DbError> {
+        let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+        self.run(move |conn| DialogEntity::delete_dialog(&chat_id, &user_id, conn).map(|_| ()))
+            .await
+    }
+
+    async fn set_dialog_keyboard(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        message_id: i64,
+    ) -> Result<(), DbError> {
+        let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+        self.run(move |conn| {
+            DialogEntity::set_keyboard_message(&chat_id, &user_id, message_id, conn)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl HistoryStore for Database {
+    async fn add_expense_record(
+        &self,
+        user_id: &str,
+        ledger_id: &str,
+        amount: f32,
+    ) -> Result<(), DbError> {
+        let (user_id, ledger_id) = (user_id.to_string(), ledger_id.to_string());
+        self.run(move |conn| {
+            HistoryRepository::add_expense_record(user_id, ledger_id, amount, conn)
+        })
+        .await
+    }
+
+    async fn add_expense_record_with_splits(
+        &self,
+        record: NewHistoryRecord,
+        split_amounts: Vec<(String, f32)>,
+    ) -> Result<(), DbError> {
+        self.run(move |conn| {
+            HistoryRepository::add_expense_record_with_splits(record, &split_amounts, conn)
+        })
+        .await
+    }
+
+    async fn update_latest_expense_record(
+        &self,
+        user_id: &str,
+        history_patch: HistoryPatch,
+    ) -> Result<(), DbError> {
+        let user_id = user_id.to_string();
+        self.run(move |conn| {
+            HistoryRepository::update_latest_expense_record(user_id, &history_patch, conn)
+        })
+        .await
+    }
+
+    async fn get_ledger_records(&self, ledger_id: &str) -> Result<Vec<HistoryEntity>, DbError> {
+        let ledger_id = ledger_id.to_string();
+        self.run(move |conn| HistoryRepository::get_ledger_records(&ledger_id, conn))
+            .await
+    }
+
+    async fn get_ledger_debts(
+        &self,
+        ledger_id: &str,
+    ) -> Result<Vec<(String, String, f32)>, DbError> {
+        let ledger_id = ledger_id.to_string();
+        self.run(move |conn| SplitRepository::get_ledger_debts(&ledger_id, conn))
+            .await
+    }
+}
+
+#[async_trait]
+impl LedgerStore for Database {
+    async fn get_or_create_ledger(&self, ledger_id: &str) -> Result<LedgerEntity, DbError> {
+        let ledger_id = ledger_id.to_string();
+        self.run(move |conn| LedgerEntity::get_or_create(&ledger_id, conn))
+            .await
+    }
+
+    async fn find_ledger_by_invite_code(&self, invite_code: &str) -> Result<LedgerEntity, DbError> {
+        let invite_code = invite_code.to_string();
+        self.run(move |conn| LedgerEntity::find_by_invite_code(&invite_code, conn))
+            .await
+    }
+}
+
+#[async_trait]
+impl UpdateStore for Database {
+    async fn claim_update(&self, update_id: i64) -> Result<bool, DbError> {
+        self.run(move |conn| UpdateRepository::claim(update_id, conn))
+            .await
+    }
+
+    async fn is_update_processed(&self, update_id: i64) -> Result<bool, DbError> {
+        self.run(move |conn| UpdateRepository::is_processed(update_id, conn))
+            .await
+    }
+
+    async fn prune_updates(&self, below_update_id: i64) -> Result<usize, DbError> {
+        self.run(move |conn| UpdateRepository::prune(below_update_id, conn))
+            .await
+    }
+
+    async fn confirm_updates(&self, next_update_id: i64) -> Result<(), DbError> {
+        self.run(move |conn| UpdateRepository::confirm(next_update_id, conn))
+            .await
+    }
+
+    async fn next_update_id(&self) -> Result<Option<i64>, DbError> {
+        self.run(UpdateRepository::next_update_id).await
+    }
+}
+
+#[async_trait]
+impl HealthStore for Database {
+    async fn ping(&self) -> Result<(), DbError> {
+        self.run(|conn| sql_query("SELECT 1").execute(conn).map(|_| ()))
+            .await
+    }
+}
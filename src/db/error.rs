@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Formatter;
+
+use diesel::result::Error as QueryError;
+use r2d2::Error as PoolError;
+use tokio::task::JoinError;
+
+#[derive(Debug)]
+pub enum DbError {
+    Query(QueryError),
+    Pool(PoolError),
+    Interrupted(JoinError),
+}
+
+impl From<QueryError> for DbError {
+    fn from(error: QueryError) -> Self {
+        DbError::Query(error)
+    }
+}
+
+impl From<PoolError> for DbError {
+    fn from(error: PoolError) -> Self {
+        DbError::Pool(error)
+    }
+}
+
+impl From<JoinError> for DbError {
+    fn from(error: JoinError) -> Self {
+        DbError::Interrupted(error)
+    }
+}
+
+impl Error for DbError {}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Query(err) => err.fmt(f),
+            DbError::Pool(err) => err.fmt(f),
+            DbError::Interrupted(err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fmt_query_error() {
+        let error = DbError::from(QueryError::NotFound);
+        assert_eq!(format!("Fail due to {}", error), "Fail due to NotFound");
+    }
+}
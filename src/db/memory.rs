@@ -0,0 +1,498 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use diesel::result::{DatabaseErrorKind, Error as QueryError};
+
+use crate::bot::dialogs::{Command, Start};
+use crate::db::error::DbError;
+use crate::db::models::dialog::DialogEntity;
+use crate::db::models::history::{HistoryEntity, HistoryPatch, NewHistoryRecord};
+use crate::db::models::ledger::LedgerEntity;
+use crate::db::models::split::SplitEntity;
+use crate::db::models::user::UserEntity;
+use crate::db::store::{
+    DialogStore, HealthStore, HistoryStore, LedgerStore, UpdateStore, UserStore,
+};
+
+type Result<T> = std::result::Result<T, DbError>;
+
+/// Store keeping everything in memory, it lets the bot run without a database, e.g. in tests.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    users: HashMap<String, UserEntity>,
+    dialogs: HashMap<(String, String), DialogEntity>,
+    history: Vec<HistoryEntity>,
+    splits: Vec<SplitEntity>,
+    ledgers: HashMap<String, LedgerEntity>,
+    processed_updates: BTreeSet<i64>,
+    next_update_id: Option<i64>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // state is consistent after every operation, so a panic of another thread does not harm it
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn not_found<T>() -> Result<T> {
+    Err(DbError::Query(QueryError::NotFound))
+}
+
+fn unique_violation<T>(message: String) -> Result<T> {
+    Err(DbError::Query(QueryError::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new(message),
+    )))
+}
+
+#[async_trait]
+impl UserStore for InMemoryStore {
+    async fn get_user(&self, user_id: &str) -> Result<UserEntity> {
+        match self.state().users.get(user_id) {
+            Some(user) => Ok(user.clone()),
+            None => not_found(),
+        }
+    }
+
+    async fn get_users_by_ids(&self, user_ids: Vec<String>) -> Result<Vec<UserEntity>> {
+        let state = self.state();
+        Ok(user_ids
+            .iter()
+            .filter_map(|user_id| state.users.get(user_id).cloned())
+            .collect())
+    }
+
+    async fn find_ledger_member(&self, ledger_id: &str, username: &str) -> Result<UserEntity> {
+        let username = username.to_lowercase();
+        let state = self.state();
+        match state.users.values().find(|user| {
+            user.username.as_deref() == Some(username.as_str())
+                && (user.ledger() == ledger_id
+                    || state
+                        .history
+                        .iter()
+                        .any(|record| record.ledger_id == ledger_id && record.user_id == user.id))
+        }) {
+            Some(user) => Ok(user.clone()),
+            None => not_found(),
+        }
+    }
+
+    async fn save_user(&self, user_id: &str) -> Result<UserEntity> {
+        let mut state = self.state();
+        if state.users.contains_key(user_id) {
+            return unique_violation(format!("user {} already exists", user_id));
+        }
+        let user = UserEntity::new(user_id.to_string());
+        let _ = state.users.insert(user_id.to_string(), user.clone());
+        let _ = state
+            .dialogs
+            .entry((user_id.to_string(), user_id.to_string()))
+            .or_insert_with(|| {
+                DialogEntity::new(
+                    user_id.to_string(),
+                    user_id.to_string(),
+                    Command::Start.to_string(),
+                    Some(Start::CurrencySelection.to_string()),
+                )
+            });
+        Ok(user)
+    }
+
+    async fn is_registered(&self, user_id: &str) -> Result<bool> {
+        Ok(self.state().users.contains_key(user_id))
+    }
+
+    async fn update_currency(&self, currency: &str, user_id: &str) -> Result<usize> {
+        match self.state().users.get_mut(user_id) {
+            Some(user) => {
+                user.currency = Some(currency.to_string());
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    async fn update_ledger(&self, ledger_id: Option<&str>, user_id: &str) -> Result<usize> {
+        match self.state().users.get_mut(user_id) {
+            Some(user) => {
+                user.ledger_id = ledger_id.map(str::to_string);
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    async fn update_names(
+        &self,
+        username: Option<&str>,
+        first_name: &str,
+        user_id: &str,
+    ) -> Result<usize> {
+        match self.state().users.get_mut(user_id) {
+            Some(user) => {
+                user.username = username.map(str::to_lowercase);
+                user.first_name = Some(first_name.to_string());
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+#[async_trait]
+impl DialogStore for InMemoryStore {
+    async fn get_user_dialog(&self, chat_id: &str, user_id: &str) -> Result<DialogEntity> {
+        match self
+            .state()
+            .dialogs
+            .get(&(chat_id.to_string(), user_id.to_string()))
+        {
+            Some(dialog) => Ok(dialog.clone()),
+            None => not_found(),
+        }
+    }
+
+    async fn save_dialog(&self, dialog: DialogEntity) -> Result<()> {
+        let _ = self
+            .state()
+            .dialogs
+            .insert((dialog.chat_id.clone(), dialog.user_id.clone()), dialog);
+        Ok(())
+    }
+
+    async fn delete_dialog(&self, chat_id: &str, user_id: &str) -> Result<()> {
+        let _ = self
+            .state()
+            .dialogs
+            .remove(&(chat_id.to_string(), user_id.to_string()));
+        Ok(())
+    }
+
+    async fn set_dialog_keyboard(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        message_id: i64,
+    ) -> Result<()> {
+        match self
+            .state()
+            .dialogs
+            .get_mut(&(chat_id.to_string(), user_id.to_string()))
+        {
+            Some(dialog) => {
+                dialog.keyboard_message_id = Some(message_id);
+                Ok(())
+            }
+            None => not_found(),
+        }
+    }
+}
+
+impl State {
+    fn add_record(&mut self, record: NewHistoryRecord) -> i32 {
+        let id = self
+            .history
+            .iter()
+            .map(|record| record.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.history.push(HistoryEntity {
+            id,
+            user_id: record.user_id,
+            amount: record.amount,
+            category: record.category,
+            created: record.created,
+            updated: None,
+            ledger_id: record.ledger_id,
+        });
+        id
+    }
+}
+
+#[async_trait]
+impl HistoryStore for InMemoryStore {
+    async fn add_expense_record(&self, user_id: &str, ledger_id: &str, amount: f32) -> Result<()> {
+        let _ = self.state().add_record(NewHistoryRecord::new(
+            user_id.to_string(),
+            ledger_id.to_string(),
+            amount,
+            None,
+        ));
+        Ok(())
+    }
+
+    async fn add_expense_record_with_splits(
+        &self,
+        record: NewHistoryRecord,
+        split_amounts: Vec<(String, f32)>,
+    ) -> Result<()> {
+        let mut state = self.state();
+        let history_id = state.add_record(record);
+        for (member, amount) in split_amounts {
+            state
+                .splits
+                .push(SplitEntity::new(history_id, member, amount));
+        }
+        Ok(())
+    }
+
+    async fn update_latest_expense_record(
+        &self,
+        user_id: &str,
+        history_patch: HistoryPatch,
+    ) -> Result<()> {
+        let mut state = self.state();
+        let latest = state
+            .history
+            .iter_mut()
+            .filter(|record| record.user_id == user_id)
+            .max_by_key(|record| (record.created, record.id));
+        match latest {
+            Some(record) => {
+                if let Some(amount) = history_patch.amount {
+                    record.amount = amount;
+                }
+                if let Some(category) = history_patch.category {
+                    record.category = Some(category);
+                }
+                record.updated = Some(history_patch.updated);
+                Ok(())
+            }
+            None => not_found(),
+        }
+    }
+
+    async fn get_ledger_records(&self, ledger_id: &str) -> Result<Vec<HistoryEntity>> {
+        let mut records = self
+            .state()
+            .history
+            .iter()
+            .filter(|record| record.ledger_id == ledger_id)
+            .cloned()
+            .collect::<Vec<HistoryEntity>>();
+        records.sort_by_key(|record| (record.created, record.id));
+        Ok(records)
+    }
+
+    async fn get_ledger_debts(&self, ledger_id: &str) -> Result<Vec<(String, String, f32)>> {
+        let state = self.state();
+        Ok(state
+            .splits
+            .iter()
+            .filter_map(|split| {
+                state
+                    .history
+                    .iter()
+                    .find(|record| record.id == split.history_id)
+                    .filter(|record| record.ledger_id == ledger_id)
+                    .filter(|record| record.user_id != split.user_id)
+                    .map(|record| (record.user_id.clone(), split.user_id.clone(), split.amount))
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl LedgerStore for InMemoryStore {
+    async fn get_or_create_ledger(&self, ledger_id: &str) -> Result<LedgerEntity> {
+        Ok(self
+            .state()
+            .ledgers
+            .entry(ledger_id.to_string())
+            .or_insert_with(|| LedgerEntity::new(ledger_id.to_string()))
+            .clone())
+    }
+
+    async fn find_ledger_by_invite_code(&self, invite_code: &str) -> Result<LedgerEntity> {
+        let invite_code = invite_code.to_uppercase();
+        match self
+            .state()
+            .ledgers
+            .values()
+            .find(|ledger| ledger.invite_code == invite_code)
+        {
+            Some(ledger) => Ok(ledger.clone()),
+            None => not_found(),
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateStore for InMemoryStore {
+    async fn claim_update(&self, update_id: i64) -> Result<bool> {
+        Ok(self.state().processed_updates.insert(update_id))
+    }
+
+    async fn is_update_processed(&self, update_id: i64) -> Result<bool> {
+        Ok(self.state().processed_updates.contains(&update_id))
+    }
+
+    async fn prune_updates(&self, below_update_id: i64) -> Result<usize> {
+        let mut state = self.state();
+        let kept = state.processed_updates.split_off(&below_update_id);
+        let pruned = state.processed_updates.len();
+        state.processed_updates = kept;
+        Ok(pruned)
+    }
+
+    async fn confirm_updates(&self, next_update_id: i64) -> Result<()> {
+        self.state().next_update_id = Some(next_update_id);
+        Ok(())
+    }
+
+    async fn next_update_id(&self) -> Result<Option<i64>> {
+        Ok(self.state().next_update_id)
+    }
+}
+
+#[async_trait]
+impl HealthStore for InMemoryStore {
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn saved_user_chooses_currency() {
+        let store = InMemoryStore::new();
+
+        let _ = store.save_user("user_id").await.unwrap();
+
+        assert!(store.is_registered("user_id").await.unwrap());
+        assert!(store.save_user("user_id").await.is_err());
+        let dialog = store.get_user_dialog("user_id", "user_id").await.unwrap();
+        assert_eq!(dialog.step, Some("CurrencySelection".to_string()));
+        store
+            .set_dialog_keyboard("user_id", "user_id", 7)
+            .await
+            .unwrap();
+        let dialog = store.get_user_dialog("user_id", "user_id").await.unwrap();
+        assert_eq!(dialog.keyboard_message_id, Some(7));
+    }
+
+    #[tokio::test]
+    async fn latest_record_is_patched() {
+        let store = InMemoryStore::new();
+        store
+            .add_expense_record("user_id", "user_id", 10.0)
+            .await
+            .unwrap();
+        store
+            .add_expense_record("user_id", "user_id", 20.0)
+            .await
+            .unwrap();
+
+        store
+            .update_latest_expense_record(
+                "user_id",
+                HistoryPatch::new(None, Some("food".to_string())),
+            )
+            .await
+            .unwrap();
+
+        let records = store.get_ledger_records("user_id").await.unwrap();
+        assert_eq!(records[0].category, None);
+        assert_eq!(records[1].category, Some("food".to_string()));
+        assert_eq!(records[1].amount, 20.0);
+    }
+
+    #[tokio::test]
+    async fn debts_skip_parts_of_payer() {
+        let store = InMemoryStore::new();
+        store
+            .add_expense_record_with_splits(
+                NewHistoryRecord::new("alice".to_string(), "group".to_string(), 90.0, None),
+                vec![
+                    ("alice".to_string(), 30.0),
+                    ("bob".to_string(), 30.0),
+                    ("carol".to_string(), 30.0),
+                ],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_ledger_debts("group").await.unwrap(),
+            vec![
+                ("alice".to_string(), "bob".to_string(), 30.0),
+                ("alice".to_string(), "carol".to_string(), 30.0)
+            ]
+        );
+        assert!(store.get_ledger_debts("other").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_ledger_members_are_found() {
+        let store = InMemoryStore::new();
+        for user_id in &["alice", "bob", "carol"] {
+            let _ = store.save_user(user_id).await.unwrap();
+            let _ = store
+                .update_names(Some(user_id), "Name", user_id)
+                .await
+                .unwrap();
+        }
+        let _ = store.update_ledger(Some("alice"), "bob").await.unwrap();
+        store
+            .add_expense_record("carol", "group", 10.0)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.find_ledger_member("alice", "Bob").await.unwrap().id,
+            "bob"
+        );
+        assert!(store.find_ledger_member("alice", "carol").await.is_err());
+        assert_eq!(
+            store.find_ledger_member("group", "carol").await.unwrap().id,
+            "carol"
+        );
+        assert!(store.find_ledger_member("group", "bob").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn update_is_claimed_once() {
+        let store = InMemoryStore::new();
+
+        assert!(store.claim_update(5).await.unwrap());
+        assert!(store.claim_update(3).await.unwrap());
+        assert!(!store.claim_update(5).await.unwrap());
+        assert_eq!(store.next_update_id().await.unwrap(), None);
+
+        store.confirm_updates(4).await.unwrap();
+        assert_eq!(store.next_update_id().await.unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn pruned_update_is_claimed_again() {
+        let store = InMemoryStore::new();
+        for update_id in 1..=4 {
+            assert!(store.claim_update(update_id).await.unwrap());
+        }
+
+        assert_eq!(store.prune_updates(3).await.unwrap(), 2);
+
+        assert!(!store.is_update_processed(1).await.unwrap());
+        assert!(store.is_update_processed(3).await.unwrap());
+        assert!(store.claim_update(1).await.unwrap());
+        assert!(!store.claim_update(3).await.unwrap());
+    }
+}
@@ -1,22 +1,65 @@
+#[cfg(feature = "sqlite")]
 use diesel::{
-    r2d2::{ConnectionManager, Pool as R2D2Pool, PooledConnection},
-    PgConnection, RunQueryDsl,
+    connection::SimpleConnection,
+    r2d2::{CustomizeConnection, Error as PoolError},
+};
+use diesel::{
+    r2d2::{
+        event::{AcquireEvent, CheckinEvent, CheckoutEvent, ReleaseEvent},
+        ConnectionManager, HandleEvent, Pool as R2D2Pool, PooledConnection,
+    },
+    RunQueryDsl,
 };
 use log::info;
 use std::fmt;
 
-use crate::db::schema::{dialogs::dsl::dialogs, history::dsl::history, users::dsl::users};
+use crate::db::schema::{
+    dialogs::dsl::dialogs, history::dsl::history, ledgers::dsl::ledgers,
+    processed_updates::dsl::processed_updates, splits::dsl::splits,
+    update_offset::dsl::update_offset, users::dsl::users,
+};
+
+use crate::db::error::DbError;
+use crate::metrics;
 
 mod schema;
 
+pub mod database;
+pub mod error;
+pub mod memory;
 pub mod models;
+pub mod store;
+
+#[cfg(test)]
+pub mod test_helpers;
 
+#[cfg(all(feature = "postgres", feature = "sqlite"))]
+compile_error!("build with `--no-default-features --features sqlite` to store data in SQLite");
+
+#[cfg(not(any(feature = "postgres", feature = "sqlite")))]
+compile_error!("either `postgres` or `sqlite` feature has to be enabled");
+
+#[cfg(feature = "postgres")]
 embed_migrations!();
 
-pub type Connection = PooledConnection<ConnectionManager<PgConnection>>;
+#[cfg(feature = "sqlite")]
+embed_migrations!("migrations_sqlite");
+
+/// Connection to the database selected by the `postgres` or `sqlite` feature.
+#[cfg(feature = "postgres")]
+pub type BackendConnection = diesel::PgConnection;
+
+#[cfg(feature = "sqlite")]
+pub type BackendConnection = diesel::SqliteConnection;
 
-pub type Pool = R2D2Pool<ConnectionManager<PgConnection>>;
+pub type Connection = PooledConnection<ConnectionManager<BackendConnection>>;
 
+pub type Pool = R2D2Pool<ConnectionManager<BackendConnection>>;
+
+/// Maximum number of connections of the pool unless configured otherwise.
+pub const DEFAULT_POOL_SIZE: u32 = 10;
+
+#[derive(Clone)]
 pub struct DbConnectionPool {
     pool: Pool,
 }
@@ -31,24 +74,116 @@ impl fmt::Debug for DbConnectionPool {
 
 impl DbConnectionPool {
     pub fn new(url: &str) -> Self {
+        Self::with_pool_size(url, DEFAULT_POOL_SIZE)
+    }
+
+    /// pool opening up to `pool_size` connections to the database
+    pub fn with_pool_size(url: &str, pool_size: u32) -> Self {
         info!("Migrating and configurating database...");
-        let manager = ConnectionManager::<PgConnection>::new(url);
-        let pool = R2D2Pool::builder()
-            .build(manager)
-            .expect("Failed to create pool.");
+        let manager = ConnectionManager::<BackendConnection>::new(url);
+        let builder = R2D2Pool::builder()
+            .max_size(pool_size)
+            .event_handler(Box::new(PoolMetrics));
+        #[cfg(feature = "sqlite")]
+        let builder = builder.connection_customizer(Box::new(SqlitePragmas));
+        let pool = builder.build(manager).expect("Failed to create pool.");
         embedded_migrations::run(&pool.get().expect("Failed to get connection."))
             .expect("Failed to run migrations");
         DbConnectionPool { pool }
     }
 
-    pub fn establish_connection(&self) -> Connection {
-        self.pool.get().expect("Can not get connection from pool")
+    /// waits for a free connection, fails when none is released within the pool timeout
+    pub fn establish_connection(&self) -> Result<Connection, DbError> {
+        Ok(self.pool.get()?)
+    }
+}
+
+/// Tracks open and checked out connections of the pool in metrics.
+#[derive(Debug, Clone, Copy)]
+struct PoolMetrics;
+
+impl HandleEvent for PoolMetrics {
+    fn handle_acquire(&self, _event: AcquireEvent) {
+        metrics::DB_POOL_CONNECTIONS.inc();
+    }
+
+    fn handle_release(&self, _event: ReleaseEvent) {
+        metrics::DB_POOL_CONNECTIONS.dec();
+    }
+
+    fn handle_checkout(&self, _event: CheckoutEvent) {
+        metrics::DB_POOL_CONNECTIONS_IN_USE.inc();
+    }
+
+    fn handle_checkin(&self, _event: CheckinEvent) {
+        metrics::DB_POOL_CONNECTIONS_IN_USE.dec();
+    }
+}
+
+/// Enforces foreign keys, which SQLite ignores by default, and makes connections of the pool
+/// wait for each other instead of failing while the database file is locked.
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, Copy)]
+struct SqlitePragmas;
+
+#[cfg(feature = "sqlite")]
+impl CustomizeConnection<BackendConnection, PoolError> for SqlitePragmas {
+    fn on_acquire(&self, conn: &mut BackendConnection) -> Result<(), PoolError> {
+        conn.batch_execute(
+            "PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;",
+        )
+        .map_err(PoolError::QueryError)
     }
 }
 
 pub fn clear_tables(conn: &Connection) -> usize {
     let dialogs_deleted = diesel::delete(dialogs).execute(conn);
+    let splits_deleted = diesel::delete(splits).execute(conn);
     let history_records_deleted = diesel::delete(history).execute(conn);
+    let ledgers_deleted = diesel::delete(ledgers).execute(conn);
     let users_deleted = diesel::delete(users).execute(conn);
-    dialogs_deleted.unwrap_or(0) + users_deleted.unwrap_or(0) + history_records_deleted.unwrap_or(0)
+    let updates_deleted = diesel::delete(processed_updates).execute(conn);
+    let offset_deleted = diesel::delete(update_offset).execute(conn);
+    dialogs_deleted.unwrap_or(0)
+        + users_deleted.unwrap_or(0)
+        + history_records_deleted.unwrap_or(0)
+        + ledgers_deleted.unwrap_or(0)
+        + splits_deleted.unwrap_or(0)
+        + updates_deleted.unwrap_or(0)
+        + offset_deleted.unwrap_or(0)
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::db::models::history::{HistoryRepository, NewHistoryRecord};
+    use crate::db::models::update::UpdateRepository;
+    use crate::db::models::user::UserEntity;
+
+    #[test]
+    fn sqlite_database_is_migrated() {
+        let path = env::temp_dir().join(format!("bot-{}.sqlite", std::process::id()));
+        let pool = DbConnectionPool::new(path.to_str().unwrap());
+        let conn = pool.establish_connection().unwrap();
+
+        let _ = UserEntity::save_user("user_id", &conn).unwrap();
+        HistoryRepository::add_expense_record_with_splits(
+            NewHistoryRecord::new("user_id".to_string(), "user_id".to_string(), 90.0, None),
+            &[("user_id".to_string(), 90.0)],
+            &conn,
+        )
+        .unwrap();
+        assert!(UpdateRepository::claim(1, &conn).unwrap());
+        assert!(!UpdateRepository::claim(1, &conn).unwrap());
+        UpdateRepository::confirm(2, &conn).unwrap();
+        UpdateRepository::confirm(3, &conn).unwrap();
+        assert_eq!(UpdateRepository::next_update_id(&conn).unwrap(), Some(3));
+        assert_eq!(clear_tables(&conn), 5);
+
+        drop(conn);
+        drop(pool);
+        let _ = std::fs::remove_file(path);
+    }
 }
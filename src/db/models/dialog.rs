@@ -1,12 +1,15 @@
 use std::result;
 
 use crate::db::schema::dialogs;
+use crate::log::redact::Id;
 use diesel::prelude::*;
 use diesel::result::Error;
 use log::{error, info};
 
 use crate::db::schema::dialogs::columns::{
-    command as command_column, step as step_column, user_id as user_id_column,
+    chat_id as chat_id_column, command as command_column,
+    keyboard_message_id as keyboard_message_id_column, step as step_column,
+    user_id as user_id_column,
 };
 
 use crate::db::Connection;
@@ -15,32 +18,47 @@ use crate::db::dialogs as dialogs_table;
 
 type Result<T> = result::Result<T, Error>;
 
-#[derive(Debug, Queryable, AsChangeset, PartialEq, Insertable)]
+/// State of the dialog of the user in the chat. For private chats `chat_id` equals `user_id`.
+#[derive(Debug, Clone, Queryable, AsChangeset, PartialEq, Insertable)]
 #[table_name = "dialogs"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct DialogEntity {
     pub user_id: String,
     pub command: String,
     pub step: Option<String>,
+    pub chat_id: String,
+    /// message with the keyboard the current step is answered by, it is gone with the next step
+    pub keyboard_message_id: Option<i64>,
 }
 
 impl DialogEntity {
-    pub fn new(user_id: String, command: String, step: Option<String>) -> Self {
+    pub fn new(chat_id: String, user_id: String, command: String, step: Option<String>) -> Self {
         DialogEntity {
             user_id,
             command,
             step,
+            chat_id,
+            keyboard_message_id: None,
         }
     }
 
     pub fn save_dialog(dialog: &DialogEntity, conn: &Connection) -> Result<()> {
-        info!("insert or update of dialog {:?}", dialog);
-        match diesel::insert_into(dialogs_table)
+        info!(
+            "insert or update of dialog {} at step {:?} for user: {} in chat: {}",
+            dialog.command,
+            dialog.step,
+            Id(&dialog.user_id),
+            Id(&dialog.chat_id)
+        );
+        #[cfg(feature = "postgres")]
+        let statement = diesel::insert_into(dialogs_table)
             .values(dialog)
-            .on_conflict(user_id_column)
+            .on_conflict((chat_id_column, user_id_column))
             .do_update()
-            .set(dialog)
-            .execute(conn)
-        {
+            .set(dialog);
+        #[cfg(feature = "sqlite")]
+        let statement = diesel::replace_into(dialogs_table).values(dialog);
+        match statement.execute(conn) {
             Ok(_) => Ok(()),
             Err(err) => {
                 error!("failed to insert or update dialog: {}", err);
@@ -49,9 +67,18 @@ impl DialogEntity {
         }
     }
 
-    pub fn get_user_dialog(user_id: &str, conn: &Connection) -> Result<DialogEntity> {
-        info!("get dialog for user: {} ", user_id);
+    pub fn get_user_dialog(
+        chat_id: &str,
+        user_id: &str,
+        conn: &Connection,
+    ) -> Result<DialogEntity> {
+        info!(
+            "get dialog for user: {} in chat: {}",
+            Id(user_id),
+            Id(chat_id)
+        );
         match dialogs_table
+            .filter(chat_id_column.eq(chat_id))
             .filter(user_id_column.eq(user_id))
             .first::<DialogEntity>(conn)
         {
@@ -64,21 +91,83 @@ impl DialogEntity {
         }
     }
 
+    /// removes the dialog, returns number of removed dialogs
+    pub fn delete_dialog(chat_id: &str, user_id: &str, conn: &Connection) -> Result<usize> {
+        info!(
+            "delete dialog for user: {} in chat: {}",
+            Id(user_id),
+            Id(chat_id)
+        );
+        match diesel::delete(
+            dialogs_table
+                .filter(chat_id_column.eq(chat_id))
+                .filter(user_id_column.eq(user_id)),
+        )
+        .execute(conn)
+        {
+            Ok(deleted) => Ok(deleted),
+            Err(err) => {
+                error!("failed to delete dialog: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// keeps the message with the keyboard sent for the current step of the dialog
+    pub fn set_keyboard_message(
+        chat_id: &str,
+        user_id: &str,
+        message_id: i64,
+        conn: &Connection,
+    ) -> Result<()> {
+        info!(
+            "set keyboard message of dialog for user: {} in chat: {}",
+            Id(user_id),
+            Id(chat_id)
+        );
+        let updated_row = diesel::update(
+            dialogs_table
+                .filter(chat_id_column.eq(chat_id))
+                .filter(user_id_column.eq(user_id)),
+        )
+        .set(keyboard_message_id_column.eq(message_id))
+        .execute(conn);
+        match updated_row {
+            Ok(0) => Err(Error::NotFound),
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to set keyboard message of dialog: {}", err);
+                Err(err)
+            }
+        }
+    }
+
     pub fn update_dialog(dialog_entity: &DialogEntity, conn: &Connection) -> Result<()> {
-        info!("update dialog dialog entity: {:?}", dialog_entity);
-        let updated_row =
-            diesel::update(dialogs_table.filter(user_id_column.eq(&dialog_entity.user_id)))
-                .set((
-                    command_column.eq(&dialog_entity.command),
-                    step_column.eq(&dialog_entity.step),
-                ))
-                .get_result::<DialogEntity>(conn);
+        info!(
+            "update dialog {} to step {:?} for user: {} in chat: {}",
+            dialog_entity.command,
+            dialog_entity.step,
+            Id(&dialog_entity.user_id),
+            Id(&dialog_entity.chat_id)
+        );
+        let updated_row = diesel::update(
+            dialogs_table
+                .filter(chat_id_column.eq(&dialog_entity.chat_id))
+                .filter(user_id_column.eq(&dialog_entity.user_id)),
+        )
+        .set((
+            command_column.eq(&dialog_entity.command),
+            step_column.eq(&dialog_entity.step),
+        ))
+        .execute(conn);
         match updated_row {
+            Ok(0) => Err(Error::NotFound),
             Ok(_) => Ok(()),
             Err(err) => {
                 error!(
                     "Can not update dialog for user: {}, {}",
-                    dialog_entity.user_id, err
+                    Id(&dialog_entity.user_id),
+                    err
                 );
                 Err(err)
             }
@@ -3,20 +3,26 @@ use std::result;
 use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel::result::Error;
+use diesel::Connection as _;
 use diesel::Insertable;
 use diesel::{insert_into, update};
 use log::{error, info};
 
 use crate::db::history as history_table;
+use crate::db::models::split::SplitEntity;
 use crate::db::schema::{
     history,
-    history::columns::{created as created_at_column, user_id as user_id_column},
+    history::columns::{
+        created as created_at_column, ledger_id as ledger_id_column, user_id as user_id_column,
+    },
 };
+use crate::db::splits as splits_table;
 use crate::db::Connection;
+use crate::log::redact::{Amount, Id};
 
 type Result<T> = result::Result<T, Error>;
 
-#[derive(Debug, Identifiable, Queryable)]
+#[derive(Debug, Clone, Identifiable, Queryable)]
 #[table_name = "history"]
 pub struct HistoryEntity {
     pub id: i32,
@@ -25,12 +31,14 @@ pub struct HistoryEntity {
     pub category: Option<String>,
     pub created: NaiveDateTime,
     pub updated: Option<NaiveDateTime>,
+    pub ledger_id: String,
 }
 
 #[derive(Debug, Insertable)]
 #[table_name = "history"]
 pub struct NewHistoryRecord {
     pub user_id: String,
+    pub ledger_id: String,
     pub amount: f32,
     pub category: Option<String>,
     pub created: NaiveDateTime,
@@ -45,9 +53,10 @@ pub struct HistoryPatch {
 }
 
 impl NewHistoryRecord {
-    pub fn new(user_id: String, amount: f32, category: Option<String>) -> Self {
+    pub fn new(user_id: String, ledger_id: String, amount: f32, category: Option<String>) -> Self {
         NewHistoryRecord {
             user_id,
+            ledger_id,
             amount,
             category,
             created: Utc::now().naive_utc(),
@@ -69,13 +78,20 @@ impl HistoryPatch {
 pub struct HistoryRepository {}
 
 impl HistoryRepository {
-    pub fn add_expense_record(user_id: String, amount: f32, conn: &Connection) -> Result<()> {
+    pub fn add_expense_record(
+        user_id: String,
+        ledger_id: String,
+        amount: f32,
+        conn: &Connection,
+    ) -> Result<()> {
         info!(
-            "inserting expense record for user {} with amount {}",
-            user_id, amount
+            "inserting expense record for user {} to ledger {} with amount {}",
+            Id(&user_id),
+            Id(&ledger_id),
+            Amount(amount)
         );
         match insert_into(history_table)
-            .values(NewHistoryRecord::new(user_id, amount, None))
+            .values(NewHistoryRecord::new(user_id, ledger_id, amount, None))
             .execute(conn)
         {
             Ok(_) => Ok(()),
@@ -86,21 +102,54 @@ impl HistoryRepository {
         }
     }
 
+    /// saves expense record together with parts of it owed by members of the ledger
+    pub fn add_expense_record_with_splits(
+        record: NewHistoryRecord,
+        split_amounts: &[(String, f32)],
+        conn: &Connection,
+    ) -> Result<()> {
+        info!(
+            "inserting record of user {} to ledger {} with amount {} split between {} members",
+            Id(&record.user_id),
+            Id(&record.ledger_id),
+            Amount(record.amount),
+            split_amounts.len()
+        );
+        let result = conn.transaction::<_, Error, _>(|| {
+            let saved = insert_record(&record, conn)?;
+            if !split_amounts.is_empty() {
+                let splits = split_amounts
+                    .iter()
+                    .map(|(member, amount)| SplitEntity::new(saved.id, member.clone(), *amount))
+                    .collect::<Vec<SplitEntity>>();
+                let _ = insert_into(splits_table).values(&splits).execute(conn)?;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to save split expense record: {}", err);
+                Err(err)
+            }
+        }
+    }
+
     pub fn update_latest_expense_record(
         user_id: String,
         history_patch: &HistoryPatch,
         conn: &Connection,
     ) -> Result<()> {
         info!(
-            "updating latest history record for user {} with patch {:?}",
-            user_id, history_patch
+            "updating latest history record for user {} with amount {:?}",
+            Id(&user_id),
+            history_patch.amount.map(Amount)
         );
         let target = history_table
             .order(created_at_column.desc())
             .filter(user_id_column.eq(user_id))
-            .first::<HistoryEntity>(conn)
-            .expect("No such dialog");
-        info!("retrieved latest record {:?}", &target);
+            .first::<HistoryEntity>(conn)?;
+        info!("retrieved latest record {}", target.id);
         match update(&target).set(history_patch).execute(conn) {
             Ok(_) => Ok(()),
             Err(err) => {
@@ -111,7 +160,7 @@ impl HistoryRepository {
     }
 
     pub fn get_all_records(user_id: String, conn: &Connection) -> Result<Vec<HistoryEntity>> {
-        info!("retrieving records for user {}", user_id,);
+        info!("retrieving records for user {}", Id(&user_id));
         match history_table.filter(user_id_column.eq(user_id)).load(conn) {
             Ok(result) => Ok(result),
             Err(err) => {
@@ -120,4 +169,35 @@ impl HistoryRepository {
             }
         }
     }
+
+    pub fn get_ledger_records(ledger_id: &str, conn: &Connection) -> Result<Vec<HistoryEntity>> {
+        info!("retrieving records of ledger {}", Id(ledger_id));
+        match history_table
+            .filter(ledger_id_column.eq(ledger_id))
+            .order(created_at_column.asc())
+            .load(conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to retrieve ledger records: {}", err);
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn insert_record(record: &NewHistoryRecord, conn: &Connection) -> Result<HistoryEntity> {
+    insert_into(history_table)
+        .values(record)
+        .get_result::<HistoryEntity>(conn)
+}
+
+/// SQLite can not return inserted rows, the record is read back within the same transaction
+#[cfg(feature = "sqlite")]
+fn insert_record(record: &NewHistoryRecord, conn: &Connection) -> Result<HistoryEntity> {
+    let _ = insert_into(history_table).values(record).execute(conn)?;
+    history_table
+        .order(history::id.desc())
+        .first::<HistoryEntity>(conn)
 }
@@ -0,0 +1,69 @@
+use std::result;
+
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::{insert_into, Insertable};
+use log::{error, info};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+use crate::db::ledgers as ledgers_table;
+use crate::db::schema::ledgers;
+use crate::db::schema::ledgers::columns::{id as id_column, invite_code as invite_code_column};
+use crate::db::Connection;
+use crate::log::redact::{Id, Text};
+
+const INVITE_CODE_LENGTH: usize = 8;
+
+type Result<T> = result::Result<T, Error>;
+
+/// Ledger shared between several users. Id of the ledger is the id of the user who created it.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[table_name = "ledgers"]
+pub struct LedgerEntity {
+    pub id: String,
+    pub invite_code: String,
+}
+
+impl LedgerEntity {
+    pub fn new(id: String) -> Self {
+        let invite_code = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(INVITE_CODE_LENGTH)
+            .collect::<String>()
+            .to_uppercase();
+        LedgerEntity { id, invite_code }
+    }
+
+    /// returns ledger with such id, creating it with a fresh invite code if needed
+    pub fn get_or_create(ledger_id: &str, conn: &Connection) -> Result<LedgerEntity> {
+        info!("get or create ledger {}", Id(ledger_id));
+        match ledgers_table
+            .filter(id_column.eq(ledger_id))
+            .first::<LedgerEntity>(conn)
+        {
+            Ok(ledger) => Ok(ledger),
+            Err(Error::NotFound) => {
+                let ledger = LedgerEntity::new(ledger_id.to_string());
+                match insert_into(ledgers_table).values(&ledger).execute(conn) {
+                    Ok(_) => Ok(ledger),
+                    Err(err) => {
+                        error!("failed to create ledger {}: {}", Id(ledger_id), err);
+                        Err(err)
+                    }
+                }
+            }
+            Err(err) => {
+                error!("failed to retrieve ledger {}: {}", Id(ledger_id), err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn find_by_invite_code(invite_code: &str, conn: &Connection) -> Result<LedgerEntity> {
+        info!("find ledger by invite code {}", Text(invite_code));
+        ledgers_table
+            .filter(invite_code_column.eq(invite_code.to_uppercase()))
+            .first::<LedgerEntity>(conn)
+    }
+}
@@ -1,3 +1,6 @@
 pub mod dialog;
 pub mod history;
+pub mod ledger;
+pub mod split;
+pub mod update;
 pub mod user;
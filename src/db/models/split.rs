@@ -0,0 +1,73 @@
+use std::result;
+
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::Insertable;
+use log::{error, info};
+
+use crate::db::history as history_table;
+use crate::db::schema::history::columns::{
+    ledger_id as history_ledger_id_column, user_id as history_user_id_column,
+};
+use crate::db::schema::splits;
+use crate::db::schema::splits::columns::{
+    amount as split_amount_column, user_id as split_user_id_column,
+};
+use crate::db::splits as splits_table;
+use crate::db::Connection;
+use crate::log::redact::Id;
+
+type Result<T> = result::Result<T, Error>;
+
+/// Part of the expense record the member owes to the member who paid it.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[table_name = "splits"]
+pub struct SplitEntity {
+    pub history_id: i32,
+    pub user_id: String,
+    pub amount: f32,
+}
+
+impl SplitEntity {
+    pub fn new(history_id: i32, user_id: String, amount: f32) -> Self {
+        SplitEntity {
+            history_id,
+            user_id,
+            amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SplitRepository {}
+
+impl SplitRepository {
+    /// returns debts of the ledger as (paid by, owed by, amount) without payer's own parts
+    pub fn get_ledger_debts(
+        ledger_id: &str,
+        conn: &Connection,
+    ) -> Result<Vec<(String, String, f32)>> {
+        info!("retrieving debts of ledger {}", Id(ledger_id));
+        match splits_table
+            .inner_join(history_table)
+            .filter(history_ledger_id_column.eq(ledger_id))
+            .filter(split_user_id_column.ne(history_user_id_column))
+            .select((
+                history_user_id_column,
+                split_user_id_column,
+                split_amount_column,
+            ))
+            .load::<(String, String, f32)>(conn)
+        {
+            Ok(debts) => Ok(debts),
+            Err(err) => {
+                error!(
+                    "failed to retrieve debts of ledger {}: {}",
+                    Id(ledger_id),
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+}
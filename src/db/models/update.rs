@@ -0,0 +1,135 @@
+use std::result;
+
+use diesel::dsl::exists;
+#[cfg(feature = "postgres")]
+use diesel::insert_into;
+#[cfg(feature = "sqlite")]
+use diesel::insert_or_ignore_into;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::Connection as _;
+use diesel::Insertable;
+use log::{error, info};
+
+use crate::db::processed_updates as processed_updates_table;
+use crate::db::schema::processed_updates;
+use crate::db::schema::processed_updates::columns::update_id as update_id_column;
+use crate::db::schema::update_offset;
+use crate::db::schema::update_offset::columns::next_update_id as next_update_id_column;
+use crate::db::update_offset as update_offset_table;
+use crate::db::Connection;
+
+type Result<T> = result::Result<T, Error>;
+
+/// Telegram update which has been processed by the bot.
+#[derive(Debug, Insertable)]
+#[table_name = "processed_updates"]
+pub struct NewProcessedUpdate {
+    pub update_id: i64,
+}
+
+/// Offset polling continues from after restart, there is a single row of it.
+#[derive(Debug, Insertable)]
+#[table_name = "update_offset"]
+pub struct UpdateOffset {
+    pub id: i32,
+    pub next_update_id: i64,
+}
+
+const OFFSET_ID: i32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct UpdateRepository {}
+
+impl UpdateRepository {
+    /// marks update as processed, returns false if it has been processed already
+    pub fn claim(update_id: i64, conn: &Connection) -> Result<bool> {
+        info!("claiming update {}", update_id);
+        let update = NewProcessedUpdate { update_id };
+        #[cfg(feature = "postgres")]
+        let statement = insert_into(processed_updates_table)
+            .values(&update)
+            .on_conflict_do_nothing();
+        #[cfg(feature = "sqlite")]
+        let statement = insert_or_ignore_into(processed_updates_table).values(&update);
+        match statement.execute(conn) {
+            Ok(inserted) => Ok(inserted == 1),
+            Err(err) => {
+                error!("failed to claim update {}: {}", update_id, err);
+                Err(err)
+            }
+        }
+    }
+
+    /// whether the update has been marked as processed
+    pub fn is_processed(update_id: i64, conn: &Connection) -> Result<bool> {
+        info!("checking update {}", update_id);
+        match diesel::select(exists(
+            processed_updates_table.filter(update_id_column.eq(update_id)),
+        ))
+        .get_result(conn)
+        {
+            Ok(processed) => Ok(processed),
+            Err(err) => {
+                error!("failed to check update {}: {}", update_id, err);
+                Err(err)
+            }
+        }
+    }
+
+    /// removes marks of updates older than the given one, Telegram does not deliver them again
+    pub fn prune(below_update_id: i64, conn: &Connection) -> Result<usize> {
+        info!("pruning updates older than {}", below_update_id);
+        match diesel::delete(processed_updates_table.filter(update_id_column.lt(below_update_id)))
+            .execute(conn)
+        {
+            Ok(deleted) => Ok(deleted),
+            Err(err) => {
+                error!("failed to prune processed updates: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// keeps the offset polling continues from, every update before it has been handled
+    pub fn confirm(next_update_id: i64, conn: &Connection) -> Result<()> {
+        info!("confirming updates before {}", next_update_id);
+        let result = conn.transaction::<_, Error, _>(|| {
+            let updated = diesel::update(update_offset_table)
+                .set(next_update_id_column.eq(next_update_id))
+                .execute(conn)?;
+            if updated == 0 {
+                let _ = diesel::insert_into(update_offset_table)
+                    .values(&UpdateOffset {
+                        id: OFFSET_ID,
+                        next_update_id,
+                    })
+                    .execute(conn)?;
+            }
+            Ok(())
+        });
+        if let Err(err) = &result {
+            error!(
+                "failed to confirm updates before {}: {}",
+                next_update_id, err
+            );
+        }
+        result
+    }
+
+    /// offset polling continues from, `None` if no update has been confirmed yet
+    pub fn next_update_id(conn: &Connection) -> Result<Option<i64>> {
+        info!("retrieving offset of updates");
+        match update_offset_table
+            .select(next_update_id_column)
+            .first::<i64>(conn)
+            .optional()
+        {
+            Ok(next_update_id) => Ok(next_update_id),
+            Err(err) => {
+                error!("failed to retrieve offset of updates: {}", err);
+                Err(err)
+            }
+        }
+    }
+}
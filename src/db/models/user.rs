@@ -9,25 +9,78 @@ use log::{error, info};
 use crate::bot::dialogs::Command;
 use crate::db::models::dialog::DialogEntity;
 use crate::db::schema::users::columns::id as id_column;
-use crate::db::schema::users::dsl::currency;
+use crate::db::schema::users::dsl::{
+    currency, first_name as first_name_column, ledger_id as ledger_id_column,
+    username as username_column,
+};
 use crate::db::Connection;
+use crate::log::redact::{Id, Text};
 
 use crate::db::dialogs as dialogs_table;
+use crate::db::history as history_table;
+use crate::db::schema::history::columns::{
+    ledger_id as history_ledger_id_column, user_id as history_user_id_column,
+};
 use crate::db::schema::users;
 use crate::db::users as users_table;
 
-#[derive(Debug, Queryable, Insertable)]
+#[derive(Debug, Clone, Queryable, Insertable)]
 #[table_name = "users"]
 pub struct UserEntity {
     pub id: String,
     pub currency: Option<String>,
+    pub ledger_id: Option<String>,
+    pub username: Option<String>,
+    pub first_name: Option<String>,
 }
 
 type Result<T> = result::Result<T, Error>;
 
 impl UserEntity {
     pub fn new(id: String) -> Self {
-        UserEntity { id, currency: None }
+        UserEntity {
+            id,
+            currency: None,
+            ledger_id: None,
+            username: None,
+            first_name: None,
+        }
+    }
+
+    /// name to show to other members, username if the user has one, first name otherwise
+    pub fn display_name(&self) -> String {
+        match (&self.username, &self.first_name) {
+            (Some(username), _) => format!("@{}", username),
+            (None, Some(first_name)) => first_name.clone(),
+            (None, None) => self.id.clone(),
+        }
+    }
+
+    /// ledger where records of the user are kept, own ledger unless user has joined another one
+    pub fn ledger(&self) -> &str {
+        self.ledger_id.as_deref().unwrap_or(&self.id)
+    }
+
+    /// ledger where records sent to the chat are kept, group chats have a ledger of their own
+    pub fn ledger_for_chat<'a>(&'a self, chat_id: &'a str) -> &'a str {
+        if chat_id == self.id {
+            self.ledger()
+        } else {
+            chat_id
+        }
+    }
+
+    pub fn get_user(user_id: &str, conn: &Connection) -> Result<UserEntity> {
+        match users_table
+            .filter(id_column.eq(user_id))
+            .first::<UserEntity>(conn)
+        {
+            Ok(user) => Ok(user),
+            Err(err) => {
+                error!("failed to get user {}: {}", Id(user_id), err);
+                Err(err)
+            }
+        }
     }
 
     pub fn get_users(conn: &Connection) -> Result<Vec<UserEntity>> {
@@ -40,14 +93,55 @@ impl UserEntity {
         }
     }
 
+    /// finds member of the ledger by username. Members share the ledger or, in group chats,
+    /// have a record in it. Usernames are looked up only among members, since a username
+    /// left by a user who has changed it may be taken by another one.
+    pub fn find_ledger_member(
+        ledger_id: &str,
+        username: &str,
+        conn: &Connection,
+    ) -> Result<UserEntity> {
+        info!("find member {} of ledger {}", Text(username), Id(ledger_id));
+        let candidates = users_table
+            .filter(username_column.eq(username.to_lowercase()))
+            .load::<UserEntity>(conn)?;
+        let candidate_ids = candidates
+            .iter()
+            .map(|candidate| candidate.id.clone())
+            .collect::<Vec<String>>();
+        let recorded = history_table
+            .filter(history_ledger_id_column.eq(ledger_id))
+            .filter(history_user_id_column.eq_any(&candidate_ids))
+            .select(history_user_id_column)
+            .load::<String>(conn)?;
+        candidates
+            .into_iter()
+            .find(|candidate| candidate.ledger() == ledger_id || recorded.contains(&candidate.id))
+            .ok_or(Error::NotFound)
+    }
+
+    pub fn get_users_by_ids(user_ids: &[String], conn: &Connection) -> Result<Vec<UserEntity>> {
+        match users_table
+            .filter(id_column.eq_any(user_ids))
+            .load::<UserEntity>(conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get users: {}", err);
+                Err(err)
+            }
+        }
+    }
+
     pub fn save_user(user_id: &str, conn: &Connection) -> Result<UserEntity> {
         let new_user = UserEntity::new(user_id.to_string());
-        info!("creating new user: {:?}", new_user);
+        info!("creating new user: {}", Id(&new_user.id));
 
         let statement = insert_into(users_table).values(&new_user);
         let result = statement.execute(conn).map(|_| {
             diesel::insert_into(dialogs_table)
                 .values(DialogEntity::new(
+                    user_id.to_string(),
                     user_id.to_string(),
                     Command::Start.to_string(),
                     Some("CurrencySelection".to_string()),
@@ -64,7 +158,7 @@ impl UserEntity {
     }
 
     pub fn is_registered(user_id: &str, conn: &Connection) -> Result<bool> {
-        info!("Check if user {} registered", user_id);
+        info!("Check if user {} registered", Id(user_id));
         match users_table
             .filter(id_column.eq(user_id))
             .load::<UserEntity>(conn)
@@ -78,7 +172,7 @@ impl UserEntity {
     }
 
     pub fn update_currency(new_currency: &str, user_id: &str, conn: &Connection) -> Result<usize> {
-        info!("currency {} update for user: {}", new_currency, user_id);
+        info!("currency {} update for user: {}", new_currency, Id(user_id));
         let target = users_table.filter(id_column.eq(user_id));
         match diesel::update(target)
             .set(currency.eq(new_currency))
@@ -86,7 +180,58 @@ impl UserEntity {
         {
             Ok(affected) => Ok(affected),
             Err(err) => {
-                error!("failed to update currency for user: {}, {}", user_id, err);
+                error!(
+                    "failed to update currency for user: {}, {}",
+                    Id(user_id),
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+
+    pub fn update_ledger(
+        new_ledger_id: Option<&str>,
+        user_id: &str,
+        conn: &Connection,
+    ) -> Result<usize> {
+        info!(
+            "ledger {:?} update for user: {}",
+            new_ledger_id.map(Id),
+            Id(user_id)
+        );
+        let target = users_table.filter(id_column.eq(user_id));
+        match diesel::update(target)
+            .set(ledger_id_column.eq(new_ledger_id))
+            .execute(conn)
+        {
+            Ok(affected) => Ok(affected),
+            Err(err) => {
+                error!("failed to update ledger for user: {}, {}", Id(user_id), err);
+                Err(err)
+            }
+        }
+    }
+
+    /// keeps names of the user as they are in Telegram, the username is removed when
+    /// the user has removed it
+    pub fn update_names(
+        new_username: Option<&str>,
+        new_first_name: &str,
+        user_id: &str,
+        conn: &Connection,
+    ) -> Result<usize> {
+        let target = users_table.filter(id_column.eq(user_id));
+        match diesel::update(target)
+            .set((
+                username_column.eq(new_username.map(str::to_lowercase)),
+                first_name_column.eq(new_first_name),
+            ))
+            .execute(conn)
+        {
+            Ok(affected) => Ok(affected),
+            Err(err) => {
+                error!("failed to update names for user: {}, {}", Id(user_id), err);
                 Err(err)
             }
         }
@@ -1,8 +1,10 @@
 table! {
-    dialogs (user_id) {
+    dialogs (chat_id, user_id) {
         user_id -> Text,
         command -> Text,
         step -> Nullable<Text>,
+        chat_id -> Text,
+        keyboard_message_id -> Nullable<BigInt>,
     }
 }
 
@@ -14,6 +16,36 @@ table! {
         category -> Nullable<Text>,
         created -> Timestamp,
         updated -> Nullable<Timestamp>,
+        ledger_id -> Text,
+    }
+}
+
+table! {
+    ledgers (id) {
+        id -> Text,
+        invite_code -> Text,
+    }
+}
+
+table! {
+    processed_updates (update_id) {
+        update_id -> BigInt,
+        processed -> Timestamp,
+    }
+}
+
+table! {
+    splits (history_id, user_id) {
+        history_id -> Integer,
+        user_id -> Text,
+        amount -> Float,
+    }
+}
+
+table! {
+    update_offset (id) {
+        id -> Integer,
+        next_update_id -> BigInt,
     }
 }
 
@@ -21,10 +53,23 @@ table! {
     users (id) {
         id -> Text,
         currency -> Nullable<Text>,
+        ledger_id -> Nullable<Text>,
+        username -> Nullable<Text>,
+        first_name -> Nullable<Text>,
     }
 }
 
 joinable!(dialogs -> users (user_id));
 joinable!(history -> users (user_id));
+joinable!(splits -> history (history_id));
+joinable!(splits -> users (user_id));
 
-allow_tables_to_appear_in_same_query!(dialogs, users, history);
+allow_tables_to_appear_in_same_query!(
+    dialogs,
+    users,
+    history,
+    ledgers,
+    processed_updates,
+    splits,
+    update_offset,
+);
@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+
+use crate::db::error::DbError;
+use crate::db::models::dialog::DialogEntity;
+use crate::db::models::history::{HistoryEntity, HistoryPatch, NewHistoryRecord};
+use crate::db::models::ledger::LedgerEntity;
+use crate::db::models::user::UserEntity;
+
+type Result<T> = std::result::Result<T, DbError>;
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn get_user(&self, user_id: &str) -> Result<UserEntity>;
+    async fn get_users_by_ids(&self, user_ids: Vec<String>) -> Result<Vec<UserEntity>>;
+    /// finds member of the ledger by username, users outside of the ledger are not found
+    async fn find_ledger_member(&self, ledger_id: &str, username: &str) -> Result<UserEntity>;
+    /// saves new user along with the dialog of choosing currency
+    async fn save_user(&self, user_id: &str) -> Result<UserEntity>;
+    async fn is_registered(&self, user_id: &str) -> Result<bool>;
+    async fn update_currency(&self, currency: &str, user_id: &str) -> Result<usize>;
+    async fn update_ledger(&self, ledger_id: Option<&str>, user_id: &str) -> Result<usize>;
+    async fn update_names(
+        &self,
+        username: Option<&str>,
+        first_name: &str,
+        user_id: &str,
+    ) -> Result<usize>;
+}
+
+#[async_trait]
+pub trait DialogStore: Send + Sync {
+    async fn get_user_dialog(&self, chat_id: &str, user_id: &str) -> Result<DialogEntity>;
+    async fn save_dialog(&self, dialog: DialogEntity) -> Result<()>;
+    async fn delete_dialog(&self, chat_id: &str, user_id: &str) -> Result<()>;
+    async fn set_dialog_keyboard(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        message_id: i64,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+pub trait HistoryStore: Send + Sync {
+    async fn add_expense_record(&self, user_id: &str, ledger_id: &str, amount: f32) -> Result<()>;
+    /// saves expense record together with parts of it owed by members of the ledger
+    async fn add_expense_record_with_splits(
+        &self,
+        record: NewHistoryRecord,
+        split_amounts: Vec<(String, f32)>,
+    ) -> Result<()>;
+    async fn update_latest_expense_record(
+        &self,
+        user_id: &str,
+        history_patch: HistoryPatch,
+    ) -> Result<()>;
+    async fn get_ledger_records(&self, ledger_id: &str) -> Result<Vec<HistoryEntity>>;
+    /// returns debts of the ledger as (paid by, owed by, amount) without payer's own parts
+    async fn get_ledger_debts(&self, ledger_id: &str) -> Result<Vec<(String, String, f32)>>;
+}
+
+#[async_trait]
+pub trait LedgerStore: Send + Sync {
+    async fn get_or_create_ledger(&self, ledger_id: &str) -> Result<LedgerEntity>;
+    async fn find_ledger_by_invite_code(&self, invite_code: &str) -> Result<LedgerEntity>;
+}
+
+#[async_trait]
+pub trait UpdateStore: Send + Sync {
+    /// marks update as processed once it has been handled, returns false if it has been
+    /// processed already
+    async fn claim_update(&self, update_id: i64) -> Result<bool>;
+    async fn is_update_processed(&self, update_id: i64) -> Result<bool>;
+    /// forgets updates older than the given one, returns number of forgotten updates
+    async fn prune_updates(&self, below_update_id: i64) -> Result<usize>;
+    /// keeps the offset polling continues from after restart
+    async fn confirm_updates(&self, next_update_id: i64) -> Result<()>;
+    async fn next_update_id(&self) -> Result<Option<i64>>;
+}
+
+#[async_trait]
+pub trait HealthStore: Send + Sync {
+    /// fails if the store can not be reached, e.g. the database is down
+    async fn ping(&self) -> Result<()>;
+}
+
+/// Everything the bot keeps, implemented by Postgres and in-memory backends.
+pub trait Store:
+    UserStore + DialogStore + HistoryStore + LedgerStore + UpdateStore + HealthStore
+{
+}
+
+impl<T> Store for T where
+    T: UserStore + DialogStore + HistoryStore + LedgerStore + UpdateStore + HealthStore
+{
+}
@@ -0,0 +1,16 @@
+use chrono::NaiveDate;
+
+use crate::db::models::history::HistoryEntity;
+
+/// record of the given member made on the given day of August 2021
+pub fn record(user_id: &str, day: u32, amount: f32, category: Option<&str>) -> HistoryEntity {
+    HistoryEntity {
+        id: day as i32,
+        user_id: user_id.to_string(),
+        amount,
+        category: category.map(str::to_string),
+        created: NaiveDate::from_ymd(2021, 8, day).and_hms(12, 0, 0),
+        updated: None,
+        ledger_id: "ledger".to_string(),
+    }
+}
@@ -20,16 +20,43 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
+use std::sync::Arc;
+
+use futures::FutureExt;
+
 use crate::bot::error::BotError;
-use crate::bot::Bot;
+use crate::bot::shutdown::shutdown_signal;
+use crate::bot::{health, Bot};
+use crate::config::Config;
 
 pub mod bot;
+pub mod config;
 pub mod db;
-mod log;
+pub mod log;
+pub mod metrics;
 pub mod telegram;
 
-pub async fn start(tg_token: String, db_url: String) -> Result<(), BotError> {
-    let bot = Bot::new(&tg_token, &db_url);
-    bot.init_bot().await;
+/// starts the bot, it receives updates via webhook if it is configured or via long polling.
+/// Runs until SIGINT or SIGTERM, then stops receiving updates and finishes handling received ones.
+pub async fn start(config: Config) -> Result<(), BotError> {
+    let bot = Arc::new(Bot::new(&config)?);
+    let shutdown = shutdown_signal().shared();
+
+    let health_server = config
+        .health_address
+        .map(|address| tokio::spawn(health::serve(address, bot.clone(), shutdown.clone())));
+    match config.webhook {
+        Some(config) => bot.init_webhook(config, shutdown).await?,
+        None => bot.init_bot(shutdown).await,
+    }
+    if let Some(health_server) = health_server {
+        if let Ok(Err(err)) = health_server.await {
+            ::log::error!("health server failed: {}", err);
+        }
+    }
+
+    // the last reference to the bot closes connections of the database pool
+    drop(bot);
+    ::log::info!("bot has stopped");
     Ok(())
 }
@@ -1,22 +1,64 @@
-use log::{Level, LevelFilter, Metadata, Record, SetLoggerError};
+use std::error::Error;
+use std::fmt;
 
-struct SimpleLogger;
+use tracing_subscriber::EnvFilter;
 
-impl log::Log for SimpleLogger {
-    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
-        metadata.level() <= Level::Info
-    }
+use crate::log::redact;
+
+/// How log lines are printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    /// every line is a JSON object along with the fields of its spans
+    Json,
+}
+
+/// Settings of logging, read by `Config` from `[log]` and env variables.
+#[derive(Clone, PartialEq)]
+pub struct LogConfig {
+    /// levels per module, e.g. `info,bot::db=debug`
+    pub filter: String,
+    pub format: LogFormat,
+    /// ids, texts and amounts are logged as they are, e.g. while debugging
+    pub sensitive_data: bool,
+    /// key of hashes of logged ids, a random one is used when it is not set
+    pub redaction_salt: Option<String>,
+}
 
-    fn log(&self, record: &Record<'_>) {
-        if self.enabled(record.metadata()) {
-            println!("{} - {}", record.level(), record.args());
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "info".to_string(),
+            format: LogFormat::Text,
+            sensitive_data: false,
+            redaction_salt: None,
         }
     }
+}
 
-    fn flush(&self) {}
+// the salt is not printed, so hashes of logged ids can not be matched against known ids
+impl fmt::Debug for LogConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogConfig")
+            .field("filter", &self.filter)
+            .field("format", &self.format)
+            .field("sensitive_data", &self.sensitive_data)
+            .field(
+                "redaction_salt",
+                &self.redaction_salt.as_ref().map(|_| "<hidden>"),
+            )
+            .finish()
+    }
 }
 
-#[allow(dead_code)]
-pub fn init_logger() -> Result<(), SetLoggerError> {
-    log::set_boxed_logger(Box::new(SimpleLogger)).map(|()| log::set_max_level(LevelFilter::Info))
+/// Installs a tracing subscriber, records of the `log` macros are forwarded to it as well.
+/// Redaction of logged values is configured along with it.
+pub fn init_logger(config: &LogConfig) -> Result<(), Box<dyn Error + Send + Sync>> {
+    redact::configure(config.sensitive_data, config.redaction_salt.as_deref());
+    let filter = EnvFilter::try_new(&config.filter)?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match config.format {
+        LogFormat::Json => builder.json().try_init(),
+        LogFormat::Text => builder.try_init(),
+    }
 }
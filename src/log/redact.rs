@@ -0,0 +1,145 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::{RwLock, RwLockReadGuard};
+
+use hmac::{Hmac, Mac, NewMac};
+use lazy_static::lazy_static;
+use rand::Rng;
+use sha2::Sha256;
+
+lazy_static! {
+    static ref REDACTION: RwLock<Redaction> = RwLock::new(Redaction::new(false, None));
+}
+
+struct Redaction {
+    /// ids, texts and amounts are logged as they are, e.g. while debugging
+    sensitive_data_logged: bool,
+    /// keeps hashed ids from being matched against hashes of all possible ids
+    salt: Vec<u8>,
+}
+
+impl Redaction {
+    /// without a salt a random one is used, ids are then correlated within one run only
+    fn new(sensitive_data_logged: bool, salt: Option<&str>) -> Self {
+        let salt = match salt {
+            Some(salt) if !salt.is_empty() => salt.as_bytes().to_vec(),
+            _ => rand::thread_rng().gen::<[u8; 32]>().to_vec(),
+        };
+        Redaction {
+            sensitive_data_logged,
+            salt,
+        }
+    }
+}
+
+/// sets how logged values are redacted, they are redacted with a random salt until then
+pub fn configure(sensitive_data_logged: bool, salt: Option<&str>) {
+    *REDACTION
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+        Redaction::new(sensitive_data_logged, salt);
+}
+
+fn redaction() -> RwLockReadGuard<'static, Redaction> {
+    REDACTION
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// first 48 bits of HMAC-SHA256 of the value, the algorithm is fixed, so hashes logged by
+/// different builds of the bot are the same
+fn keyed_hash(salt: &[u8], value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(salt).expect("HMAC takes keys of any length");
+    mac.update(value.as_bytes());
+    let hash = mac.finalize().into_bytes();
+    hash[..6]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Id of a user or a chat, logged as its salted hash, so lines of one user can be correlated
+/// without revealing who the user is.
+#[derive(Clone, Copy)]
+pub struct Id<T>(pub T);
+
+impl<T: Display> Display for Id<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let redaction = redaction();
+        if redaction.sensitive_data_logged {
+            return self.0.fmt(f);
+        }
+        write!(f, "#{}", keyed_hash(&redaction.salt, &self.0.to_string()))
+    }
+}
+
+/// Text written by a user: payloads, descriptions, feedback and usernames. Only its length
+/// is logged.
+#[derive(Clone, Copy)]
+pub struct Text<'a>(pub &'a str);
+
+impl Display for Text<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if redaction().sensitive_data_logged {
+            return self.0.fmt(f);
+        }
+        write!(f, "<{} chars>", self.0.chars().count())
+    }
+}
+
+/// Amount of money, not logged at all.
+#[derive(Clone, Copy)]
+pub struct Amount<T>(pub T);
+
+impl<T: Display> Display for Amount<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if redaction().sensitive_data_logged {
+            return self.0.fmt(f);
+        }
+        f.write_str("<amount>")
+    }
+}
+
+macro_rules! debug_as_display {
+    ($($redacted:ty),*) => {
+        $(impl<T: Display> fmt::Debug for $redacted {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                Display::fmt(self, f)
+            }
+        })*
+    };
+}
+
+// so nothing leaks when redacted values are logged inside of other values
+debug_as_display!(Id<T>, Amount<T>);
+
+impl fmt::Debug for Text<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sensitive_data_is_masked() {
+        let hashed = Id("123456789").to_string();
+
+        assert_eq!(hashed, Id(123_456_789).to_string());
+        assert_ne!(hashed, Id("987654321").to_string());
+        assert!(!hashed.contains("123456789"));
+        assert_eq!(Text("dinner with Bob").to_string(), "<15 chars>");
+        assert_eq!(Amount(90.5).to_string(), "<amount>");
+        assert_eq!(format!("{:?}", Some(Id("1"))), format!("Some({})", Id("1")));
+    }
+
+    #[test]
+    fn ids_are_hashed_by_hmac_sha256() {
+        // test case 2 of RFC 4231
+        assert_eq!(
+            keyed_hash(b"Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60"
+        );
+    }
+}
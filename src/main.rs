@@ -1,31 +1,24 @@
+use bot::config::Config;
+use bot::log::logger::init_logger;
 use bot::start;
 use dotenv::dotenv;
-use std::env;
-
-mod log;
-mod telegram;
+use std::process;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    if let Ok(env_var) = env::var("ENV") {
-        println!("Recieved value from ENV param {}", &env_var,);
-        match env_var.as_str() {
-            "DEV" => dotenv::from_filename("dev.env")
-                .expect("Failed to read env variables from test.env"),
-            "PROD" => dotenv().expect("Failed to read .env file"),
-            _ => dotenv().expect("Failed to read .env file"),
-        };
-    } else {
-        println!("Recieved no value from ENV param");
-        dotenv().ok();
-    }
-
-    log::logger::init_logger().expect("Can not run logging!");
+    dotenv().ok();
 
-    let token = env::var("TELEGRAM_BOT_TOKEN").expect("Missing TELEGRAM_BOT_TOKEN env var");
-    let db_url = env::var("DATABASE_URL").expect("Missing DATABASE_URL env var");
+    // logging is configured by the config, so its problems are printed as they are
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    init_logger(&config.log).expect("Can not run logging!");
 
-    start(token, db_url).await?;
+    start(config).await?;
 
     Ok(())
 }
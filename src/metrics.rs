@@ -0,0 +1,74 @@
+use lazy_static::lazy_static;
+use log::error;
+use prometheus::{
+    register_histogram, register_int_counter_vec, register_int_gauge, Encoder, Histogram,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+
+lazy_static! {
+    pub static ref UPDATES_RECEIVED: IntCounterVec = register_int_counter_vec!(
+        "bot_updates_received_total",
+        "Updates received from Telegram by kind",
+        &["kind"]
+    )
+    .expect("Can not register metric");
+    pub static ref COMMANDS_HANDLED: IntCounterVec = register_int_counter_vec!(
+        "bot_commands_handled_total",
+        "Messages handled by the command of the dialog they belong to",
+        &["command"]
+    )
+    .expect("Can not register metric");
+    pub static ref HANDLER_DURATION: Histogram = register_histogram!(
+        "bot_handler_duration_seconds",
+        "Time spent handling an update"
+    )
+    .expect("Can not register metric");
+    pub static ref BOT_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "bot_errors_total",
+        "Errors of handling updates by kind",
+        &["error"]
+    )
+    .expect("Can not register metric");
+    pub static ref TELEGRAM_API_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "telegram_api_errors_total",
+        "Failed requests to the Telegram Bot API by method",
+        &["method"]
+    )
+    .expect("Can not register metric");
+    pub static ref DB_POOL_CONNECTIONS: IntGauge = register_int_gauge!(
+        "db_pool_connections",
+        "Connections opened by the database pool"
+    )
+    .expect("Can not register metric");
+    pub static ref DB_POOL_CONNECTIONS_IN_USE: IntGauge = register_int_gauge!(
+        "db_pool_connections_in_use",
+        "Connections of the database pool running queries"
+    )
+    .expect("Can not register metric");
+}
+
+/// renders all metrics in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = vec![];
+    if let Err(err) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("failed to encode metrics: {}", err);
+    }
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_rendered() {
+        TELEGRAM_API_ERRORS
+            .with_label_values(&["sendMessage"])
+            .inc();
+
+        let metrics = render();
+
+        assert!(metrics.contains("# TYPE telegram_api_errors_total counter"));
+        assert!(metrics.contains(r#"telegram_api_errors_total{method="sendMessage"}"#));
+    }
+}
@@ -1,137 +1,504 @@
 use async_trait::async_trait;
-use std::error::Error;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::telegram::error::TelegramError;
-use crate::telegram::types::{EditImage, EditMessage, Image, Message};
-use reqwest::{Client, Response};
-use serde_json::{from_str, Value};
-use telegram_bot::{Api, UpdatesStream};
+use crate::metrics;
+use crate::telegram::error::{ApiError, TelegramError};
+use crate::telegram::rate_limit::RateLimiter;
+use crate::telegram::types::{
+    AnswerCallbackQuery, Document, EditImage, EditMessage, Image, InputFile, Message, ParseMode,
+    ReceivedMessage, Response, Update, User,
+};
+use futures::stream::{self, BoxStream, StreamExt};
+use log::warn;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Proxy, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde_json::{from_str, json};
+
+/// How long Telegram holds a getUpdates request open waiting for updates, in seconds.
+const POLLING_TIMEOUT: u64 = 30;
+
+/// Bot API served by Telegram, a self-hosted server can be used instead.
+pub const DEFAULT_API_URL: &str = "https://api.telegram.org";
+
+/// Pause before polling again after getUpdates has failed.
+const POLLING_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Pause before polling again when getUpdates has returned only updates which are still
+/// being handled, Telegram returns them at once until the offset moves past them.
+const POLLING_BUSY_DELAY: Duration = Duration::from_secs(1);
+
+/// How long a connection to Telegram may take to establish.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a request may take, long polling is given this on top of its own timeout.
+/// Messages which time out are not sent again, Telegram may have received them.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a request uploading a file may take, so slow uploads are not cut off and lost.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Methods which must not be repeated when Telegram may have processed them,
+/// a repeat sends the message to the chat twice.
+const NOT_IDEMPOTENT_METHODS: [&str; 3] = ["sendMessage", "sendPhoto", "sendDocument"];
+
+/// Idle connections are kept open this long, so replies do not wait for a TLS handshake.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Interval of TCP keepalive probes, they detect connections dropped by the network.
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub struct TelegramClient {
     token: String,
     domain: String,
+    http: Client,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    last_poll: Mutex<Option<Instant>>,
+}
+
+/// Proxy the requests to Telegram go through, e.g. `socks5://proxy.example.com:1080`.
+/// HTTP, HTTPS and SOCKS5 proxies are supported.
+#[derive(Clone, PartialEq)]
+pub struct ProxyConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<hidden>"))
+            .finish()
+    }
+}
+
+/// How requests which have failed for a transient reason are repeated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// repeats of the request after the first attempt
+    pub max_retries: u32,
+    /// pause before the first repeat, it doubles with each next one
+    pub base_delay: Duration,
+    /// longest wait asked by Telegram's flood control which is waited out instead of failing
+    pub max_flood_wait: Duration,
 }
 
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_flood_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// pause before repeating the request which has failed after the given number of retries,
+    /// `None` when it is not repeated. Requests which are not idempotent are repeated only
+    /// if Telegram has surely not processed them.
+    fn delay(&self, retries: u32, error: &TelegramError, idempotent: bool) -> Option<Duration> {
+        if retries >= self.max_retries || !error.is_transient() {
+            return None;
+        }
+        if !idempotent && !error.is_unprocessed() {
+            return None;
+        }
+        match error.retry_after() {
+            Some(seconds) if Duration::from_secs(seconds) > self.max_flood_wait => None,
+            Some(seconds) => Some(Duration::from_secs(seconds)),
+            None => Some(self.base_delay * 2u32.pow(retries)),
+        }
+    }
+}
+
+/// Telegram Bot API used by the bot, implemented by `TelegramClient` and by the recording fake.
 #[async_trait]
-pub trait TelegramService {
-    fn stream(&self) -> UpdatesStream;
-    async fn send_message(&self, message: &Message<'_>) -> Result<String, TelegramError>;
-    async fn send_photo(&self, message: &Message<'_>) -> Result<String, TelegramError>;
+pub trait TelegramService: Send + Sync {
+    /// polls updates from the offset, it is moved by the caller once updates are handled
+    fn stream(&self, offset: Arc<AtomicI64>) -> BoxStream<'_, Result<Update, TelegramError>>;
+    /// when getUpdates has succeeded last time, even if there were no updates
+    fn last_poll(&self) -> Option<Instant>;
+    async fn get_updates(&self, offset: i64, timeout: u64) -> Result<Vec<Update>, TelegramError>;
+    async fn get_me(&self) -> Result<String, TelegramError>;
+    async fn set_webhook(&self, webhook_url: &str, secret_token: &str)
+        -> Result<(), TelegramError>;
+    async fn delete_webhook(&self) -> Result<(), TelegramError>;
+    async fn send_message(&self, message: &Message<'_>) -> Result<ReceivedMessage, TelegramError>;
+    async fn send_photo(&self, image: &Image<'_>) -> Result<ReceivedMessage, TelegramError>;
+    async fn send_document(
+        &self,
+        document: &Document<'_>,
+    ) -> Result<ReceivedMessage, TelegramError>;
     async fn delete_message(&self, chat_id: &str, message_id: &str) -> Result<(), TelegramError>;
-    async fn edit_message_text(&self, message: &EditMessage<'_>) -> Result<(), Box<dyn Error>>;
-    async fn edit_message_image(&self, message: &EditImage<'_>) -> Result<(), Box<dyn Error>>;
+    async fn edit_message_text(
+        &self,
+        message: &EditMessage<'_>,
+    ) -> Result<ReceivedMessage, TelegramError>;
+    async fn edit_message_image(
+        &self,
+        edit_image: &EditImage<'_>,
+    ) -> Result<ReceivedMessage, TelegramError>;
+    async fn answer_callback_query(
+        &self,
+        answer: &AnswerCallbackQuery<'_>,
+    ) -> Result<(), TelegramError>;
 }
 
-#[allow(dead_code)]
 impl TelegramClient {
     pub fn new(token: String) -> TelegramClient {
+        TelegramClient::new_with(token, String::from(DEFAULT_API_URL))
+    }
+
+    /// client of the Bot API served at the url, e.g. of a self-hosted Bot API server
+    pub fn new_with(token: String, domain: String) -> TelegramClient {
         TelegramClient {
             token,
-            domain: String::from("https://api.telegram.org"),
+            domain,
+            // building fails only when TLS can not be initialized, as it would in `Client::new`
+            http: http_client(None).expect("HTTP client can not be built"),
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: RateLimiter::default(),
+            last_poll: Mutex::new(None),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn new_with(token: String, domain: String) -> TelegramClient {
-        TelegramClient { token, domain }
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
     }
 
-    pub fn stream(&self) -> UpdatesStream {
-        let api = Api::new(&self.token);
-        api.stream()
+    /// sends all requests to Telegram through the proxy, polling of updates included
+    pub fn with_proxy(mut self, proxy: &ProxyConfig) -> Result<Self, TelegramError> {
+        self.http = http_client(Some(proxy))?;
+        Ok(self)
     }
 
-    #[allow(dead_code)]
-    pub async fn send_message<'a>(
-        &'a self,
-        message: &Message<'a>,
-    ) -> Result<String, TelegramError> {
-        let url = format!("{}/bot{}/sendMessage", self.domain, self.token);
-        let resp: Response = Client::new()
-            .post(&url)
-            .json(message)
-            .send()
-            .await
-            .expect("Message has not been sent");
-        if resp.status().is_success() {
-            let resp: Value = from_str(&resp.text().await?)?;
-            let resp = &resp["result"];
-            let resp = &resp["text"];
-            let resp = resp.as_str().unwrap_or("");
-            Ok(resp.to_string())
-        } else {
-            Err(resp.text().await?.into())
-        }
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
     }
 
-    #[allow(dead_code)]
-    pub async fn send_photo<'a>(&'a self, image: &Image<'a>) -> Result<String, TelegramError> {
-        let url = format!("{}/bot{}/sendPhoto", self.domain, self.token);
-        let resp: Response = Client::new().post(&url).json(&image).send().await?;
+    /// sends request to the method of the Bot API, returns body of the successful response.
+    /// Requests failed for a transient reason are repeated with exponential backoff, flood
+    /// control is waited out as Telegram asks. Messages are sent again only if the first attempt
+    /// has not reached Telegram, so they are not duplicated. Failed requests are counted
+    /// by method, so errors of the API can be monitored.
+    async fn call<F>(&self, method: &'static str, request: F) -> Result<String, TelegramError>
+    where
+        F: Fn(&str) -> RequestBuilder,
+    {
+        let url = format!("{}/bot{}/{}", self.domain, self.token, method);
+        let idempotent = !NOT_IDEMPOTENT_METHODS.contains(&method);
+        let mut retries = 0;
+        loop {
+            let error = match send(request(&url)).await {
+                Ok(body) => return Ok(body),
+                Err(error) => error,
+            };
+            metrics::TELEGRAM_API_ERRORS
+                .with_label_values(&[method])
+                .inc();
+            match self.retry_policy.delay(retries, &error, idempotent) {
+                Some(delay) => {
+                    warn!("{} has failed, repeating in {:?}: {}", method, delay, error);
+                    tokio::time::delay_for(delay).await;
+                    retries += 1;
+                }
+                None => return Err(error),
+            }
+        }
+    }
+}
 
-        if resp.status().is_success() {
-            let resp: Value = from_str(&resp.text().await?)?;
-            let resp = &resp["result"];
-            let resp = &resp["text"];
-            let resp = resp.as_str().unwrap_or("");
-            Ok(resp.to_string())
-        } else {
-            Err(resp.text().await?.into())
+/// HTTP client shared by all requests, its pool keeps connections to Telegram alive
+fn http_client(proxy: Option<&ProxyConfig>) -> Result<Client, TelegramError> {
+    let mut builder = Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE);
+    if let Some(proxy) = proxy {
+        let mut http_proxy = Proxy::all(&proxy.url)?;
+        if let Some(username) = &proxy.username {
+            http_proxy = http_proxy.basic_auth(username, proxy.password.as_deref().unwrap_or(""));
         }
+        builder = builder.proxy(http_proxy);
+    }
+    Ok(builder.build()?)
+}
+
+/// result of the successful response of the Bot API
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T, TelegramError> {
+    Ok(from_str::<Response<T>>(body)?.result)
+}
+
+/// form uploading the file along with the fields of the request
+fn upload_form(
+    chat_id: &str,
+    field: &str,
+    file: &InputFile<'_>,
+    caption: Option<&str>,
+    parse_mode: Option<ParseMode>,
+    disable_notification: bool,
+) -> Form {
+    let mut form = Form::new()
+        .text("chat_id", chat_id.to_string())
+        .text("disable_notification", disable_notification.to_string());
+    if let InputFile::Upload { file_name, bytes } = file {
+        form = form.part(
+            field.to_string(),
+            Part::bytes(bytes.to_vec()).file_name(file_name.to_string()),
+        );
+    }
+    if let Some(caption) = caption {
+        form = form.text("caption", caption.to_string());
+    }
+    if let Some(parse_mode) = parse_mode {
+        // the mode is serialized as a JSON string, e.g. "MarkdownV2"
+        let parse_mode = json!(parse_mode);
+        form = form.text("parse_mode", parse_mode.as_str().unwrap_or("").to_string());
+    }
+    form
+}
+
+/// returns body of the successful response, error responses of the Bot API are parsed
+async fn send(request: RequestBuilder) -> Result<String, TelegramError> {
+    let resp = request.send().await?;
+    let is_success = resp.status().is_success();
+    let text = resp.text().await?;
+    if is_success {
+        return Ok(text);
+    }
+    match from_str::<ApiError>(&text) {
+        Ok(error) => Err(TelegramError::Api(error)),
+        Err(_) => Err(TelegramError::Unsuccessful(text)),
     }
+}
 
-    #[allow(dead_code)]
-    pub async fn delete_message(
+#[async_trait]
+impl TelegramService for TelegramClient {
+    /// long polls updates from the offset, Telegram forgets updates only once the offset has
+    /// moved past them, so updates which are not handled yet are delivered again after restart.
+    /// Updates returned again while they are being handled are skipped.
+    fn stream(&self, offset: Arc<AtomicI64>) -> BoxStream<'_, Result<Update, TelegramError>> {
+        let next = offset.load(Ordering::SeqCst);
+        stream::unfold(
+            (next, VecDeque::new(), false),
+            move |(mut next, mut pending, mut failed)| {
+                let offset = offset.clone();
+                async move {
+                    loop {
+                        if let Some(update) = pending.pop_front() {
+                            return Some((Ok(update), (next, pending, false)));
+                        }
+                        if failed {
+                            tokio::time::delay_for(POLLING_RETRY_DELAY).await;
+                        }
+                        let polled = self
+                            .get_updates(offset.load(Ordering::SeqCst), POLLING_TIMEOUT)
+                            .await;
+                        match polled {
+                            Ok(updates) => {
+                                let received = updates.len();
+                                pending.extend(
+                                    updates
+                                        .into_iter()
+                                        .filter(|update| update.update_id >= next),
+                                );
+                                match pending.back() {
+                                    Some(last) => next = last.update_id + 1,
+                                    None if received > 0 => {
+                                        tokio::time::delay_for(POLLING_BUSY_DELAY).await
+                                    }
+                                    None => {}
+                                }
+                                failed = false;
+                            }
+                            Err(err) => return Some((Err(err), (next, pending, true))),
+                        }
+                    }
+                }
+            },
+        )
+        .boxed()
+    }
+
+    fn last_poll(&self) -> Option<Instant> {
+        *self
+            .last_poll
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// returns updates starting from the offset, waits up to timeout seconds if there are none
+    async fn get_updates(&self, offset: i64, timeout: u64) -> Result<Vec<Update>, TelegramError> {
+        let resp = self
+            .call("getUpdates", |url| {
+                self.http
+                    .post(url)
+                    .timeout(Duration::from_secs(timeout) + REQUEST_TIMEOUT)
+                    .json(&json!({
+                        "offset": offset,
+                        "timeout": timeout,
+                        "allowed_updates": ["message", "callback_query"],
+                    }))
+            })
+            .await?;
+
+        let updates = parse(&resp)?;
+        *self
+            .last_poll
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Instant::now());
+        Ok(updates)
+    }
+
+    /// returns username of the bot, it is needed to recognize commands addressed to the bot
+    async fn get_me(&self) -> Result<String, TelegramError> {
+        let resp = self.call("getMe", |url| self.http.get(url)).await?;
+        let me: User = parse(&resp)?;
+        Ok(me.username.unwrap_or_default())
+    }
+
+    /// makes Telegram send updates to the url instead of keeping them for long polling
+    async fn set_webhook(
         &self,
-        chat_id: &str,
-        message_id: &str,
+        webhook_url: &str,
+        secret_token: &str,
     ) -> Result<(), TelegramError> {
-        let url = format!("{}/bot{}/deleteMessage", self.domain, self.token);
-        let resp: Response = Client::new()
-            .post(&url)
-            .form(&[
-                ("chat_id", &String::from(chat_id)),
-                ("message_id", &String::from(message_id)),
-            ])
-            .send()
+        let _ = self
+            .call("setWebhook", |url| {
+                self.http
+                    .post(url)
+                    .form(&[("url", webhook_url), ("secret_token", secret_token)])
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// removes webhook, updates can not be polled while it is set
+    async fn delete_webhook(&self) -> Result<(), TelegramError> {
+        let _ = self
+            .call("deleteWebhook", |url| self.http.post(url))
             .await?;
+        Ok(())
+    }
 
-        if resp.status().is_success() {
-            Ok(())
-        } else {
-            Err(resp.text().await?.into())
-        }
+    /// returns the sent message, its id is needed to edit or delete it
+    async fn send_message(&self, message: &Message<'_>) -> Result<ReceivedMessage, TelegramError> {
+        self.rate_limiter.acquire(message.chat_id).await;
+        let resp = self
+            .call("sendMessage", |url| self.http.post(url).json(message))
+            .await?;
+        parse(&resp)
     }
 
-    #[allow(dead_code)]
-    pub async fn edit_message_text<'a>(
-        &'a self,
-        message: &EditMessage<'a>,
-    ) -> Result<(), Box<dyn Error>> {
-        let url = format!("{}/bot{}/editMessageText", self.domain, self.token);
-        let resp: Response = Client::new().post(&url).json(&message).send().await?;
+    async fn send_photo(&self, image: &Image<'_>) -> Result<ReceivedMessage, TelegramError> {
+        self.rate_limiter.acquire(image.chat_id).await;
+        let resp = self
+            .call("sendPhoto", |url| {
+                let request = self.http.post(url);
+                match image.photo {
+                    InputFile::Url(_) => request.json(&image),
+                    InputFile::Upload { .. } => {
+                        request.timeout(UPLOAD_TIMEOUT).multipart(upload_form(
+                            image.chat_id,
+                            "photo",
+                            &image.photo,
+                            image.caption,
+                            image.parse_mode,
+                            image.disable_notification,
+                        ))
+                    }
+                }
+            })
+            .await?;
+        parse(&resp)
+    }
 
-        if resp.status().is_success() {
-            Ok(())
-        } else {
-            Err(resp.text().await?.into())
-        }
+    async fn send_document(
+        &self,
+        document: &Document<'_>,
+    ) -> Result<ReceivedMessage, TelegramError> {
+        self.rate_limiter.acquire(document.chat_id).await;
+        let resp = self
+            .call("sendDocument", |url| {
+                let request = self.http.post(url);
+                match document.document {
+                    InputFile::Url(_) => request.json(&document),
+                    InputFile::Upload { .. } => {
+                        request.timeout(UPLOAD_TIMEOUT).multipart(upload_form(
+                            document.chat_id,
+                            "document",
+                            &document.document,
+                            document.caption,
+                            document.parse_mode,
+                            document.disable_notification,
+                        ))
+                    }
+                }
+            })
+            .await?;
+        parse(&resp)
     }
 
-    #[allow(dead_code)]
-    pub async fn edit_message_image<'a>(
-        &'a self,
-        edit_image: &EditImage<'a>,
-    ) -> Result<(), Box<dyn Error>> {
-        let url = format!("{}/bot{}/editMessageMedia", self.domain, self.token);
-        let resp: Response = Client::new().post(&url).json(&edit_image).send().await?;
+    async fn delete_message(&self, chat_id: &str, message_id: &str) -> Result<(), TelegramError> {
+        let _ = self
+            .call("deleteMessage", |url| {
+                self.http
+                    .post(url)
+                    .form(&[("chat_id", chat_id), ("message_id", message_id)])
+            })
+            .await?;
+        Ok(())
+    }
 
-        if resp.status().is_success() {
-            Ok(())
-        } else {
-            Err(resp.text().await?.into())
-        }
+    /// returns the edited message
+    async fn edit_message_text(
+        &self,
+        message: &EditMessage<'_>,
+    ) -> Result<ReceivedMessage, TelegramError> {
+        self.rate_limiter.acquire(message.chat_id).await;
+        let resp = self
+            .call("editMessageText", |url| self.http.post(url).json(&message))
+            .await?;
+        parse(&resp)
+    }
+
+    async fn edit_message_image(
+        &self,
+        edit_image: &EditImage<'_>,
+    ) -> Result<ReceivedMessage, TelegramError> {
+        self.rate_limiter.acquire(edit_image.chat_id).await;
+        let resp = self
+            .call("editMessageMedia", |url| {
+                self.http.post(url).json(&edit_image)
+            })
+            .await?;
+        parse(&resp)
+    }
+
+    /// answers are not rate limited, they are not messages to the chat
+    async fn answer_callback_query(
+        &self,
+        answer: &AnswerCallbackQuery<'_>,
+    ) -> Result<(), TelegramError> {
+        let _ = self
+            .call("answerCallbackQuery", |url| {
+                self.http.post(url).json(&answer)
+            })
+            .await?;
+        Ok(())
     }
 }
 
@@ -141,19 +508,183 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::telegram::error::ResponseParameters;
     use crate::telegram::test_helpers::mock_send_message_success;
     use crate::telegram::types::{
-        EditImage, InlineKeyboardButton, InlineKeyboardMarkup, Media, ReplyMarkup,
+        EditImage, ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, Media,
+        ReplyKeyboardMarkup, ReplyKeyboardRemove, ReplyMarkup,
     };
 
     const TOKEN: &str = "token";
 
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_flood_wait: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn retries_are_delayed_exponentially() {
+        let policy = RetryPolicy::default();
+        let gateway_error = TelegramError::Unsuccessful("Bad Gateway".to_string());
+        let flood_wait = |seconds| {
+            TelegramError::Api(ApiError {
+                error_code: 429,
+                description: "Too Many Requests".to_string(),
+                parameters: Some(ResponseParameters {
+                    migrate_to_chat_id: None,
+                    retry_after: Some(seconds),
+                }),
+            })
+        };
+
+        assert_eq!(
+            policy.delay(0, &gateway_error, true),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            policy.delay(2, &gateway_error, true),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(policy.delay(3, &gateway_error, true), None);
+        assert_eq!(
+            policy.delay(0, &flood_wait(7), true),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(policy.delay(0, &flood_wait(600), true), None);
+    }
+
+    #[test]
+    fn messages_are_repeated_only_when_unprocessed() {
+        let policy = RetryPolicy::default();
+        let gateway_error = TelegramError::Unsuccessful("Bad Gateway".to_string());
+        let flood_wait = TelegramError::Api(ApiError {
+            error_code: 429,
+            description: "Too Many Requests".to_string(),
+            parameters: Some(ResponseParameters {
+                migrate_to_chat_id: None,
+                retry_after: Some(7),
+            }),
+        });
+
+        assert_eq!(policy.delay(0, &gateway_error, false), None);
+        assert_eq!(
+            policy.delay(0, &flood_wait, false),
+            Some(Duration::from_secs(7))
+        );
+    }
+
+    #[tokio::test]
+    async fn message_is_not_sent_again_after_gateway_error() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .with_status(502)
+            .with_body("Bad Gateway")
+            .expect(1)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url))
+            .with_retry_policy(fast_retries());
+        let result = client
+            .send_message(&Message {
+                chat_id: "414141",
+                text: "hello",
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(format!("{}", result), "Bad Gateway");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/deleteWebhook", TOKEN).as_str())
+            .with_status(502)
+            .with_body("Bad Gateway")
+            .expect(3)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url))
+            .with_retry_policy(fast_retries());
+        let result = client.delete_webhook().await.unwrap_err();
+
+        assert_eq!(format!("{}", result), "Bad Gateway");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn long_flood_wait_is_not_waited_out() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 120","parameters":{"retry_after":120}}"#;
+        let _m = mock("POST", format!("/bot{}/deleteWebhook", TOKEN).as_str())
+            .with_status(429)
+            .with_body(error)
+            .expect(1)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url))
+            .with_retry_policy(fast_retries());
+        let result = client.delete_webhook().await.unwrap_err();
+
+        assert_eq!(result.retry_after(), Some(120));
+        _m.assert();
+    }
+
+    #[test]
+    fn invalid_proxy_is_rejected() {
+        let proxy = ProxyConfig {
+            url: "not a proxy".to_string(),
+            username: None,
+            password: None,
+        };
+
+        let result = TelegramClient::new(String::from(TOKEN)).with_proxy(&proxy);
+
+        assert!(matches!(result, Err(TelegramError::NetworkError(_))));
+    }
+
+    #[test]
+    fn proxy_password_is_not_printed() {
+        let proxy = ProxyConfig {
+            url: "socks5://proxy.example.com:1080".to_string(),
+            username: Some("bot".to_string()),
+            password: Some("secret".to_string()),
+        };
+
+        assert!(TelegramClient::new(String::from(TOKEN))
+            .with_proxy(&proxy)
+            .is_ok());
+        assert!(!format!("{:?}", proxy).contains("secret"));
+    }
+
     #[test]
     fn correct_domain() {
         let telegram_client = TelegramClient::new(String::from(TOKEN));
         assert_eq!(telegram_client.domain, "https://api.telegram.org");
     }
 
+    #[tokio::test]
+    async fn get_me_success() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":{"id":414141,"is_bot":true,"first_name":"Bot","username":"expenses_bot"}}"#;
+
+        let mock = mock("GET", format!("/bot{}/getMe", TOKEN).as_str())
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let username = client.get_me().await.unwrap();
+        assert_eq!(username, "expenses_bot");
+        mock.assert();
+    }
+
     #[tokio::test]
     async fn send_message_success() {
         let url = &server_url();
@@ -170,12 +701,16 @@ mod tests {
             disable_notification: true,
             disable_web_page_preview: false,
             reply_markup: Some(&reply_markup),
+            ..Default::default()
         };
         let mock = mock_send_message_success(TOKEN, &message);
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        let sent_text_message = client.send_message(&message).await.unwrap();
-        assert_eq!(sent_text_message, text);
+        let sent_message = client.send_message(&message).await.unwrap();
+        assert_eq!(sent_message.message_id, 691);
+        assert_eq!(sent_message.chat.id, 123);
+        assert_eq!(sent_message.date, 1581200384);
+        assert_eq!(sent_message.text.as_deref(), Some(text));
         mock.assert();
     }
 
@@ -190,6 +725,7 @@ mod tests {
             disable_notification: true,
             disable_web_page_preview: false,
             reply_markup: None,
+            ..Default::default()
         };
 
         let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
@@ -201,8 +737,7 @@ mod tests {
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.send_message(&message).await.unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert_eq!(format!("{}", result), "Bad Request: chat not found (400)");
         _m.assert();
     }
 
@@ -212,8 +747,9 @@ mod tests {
         let resp = r#"{"ok":true,"result":{"message_id":691,"from":{"id":414141,"is_bot":true,"first_name":"Bot","username":"Bot"},"chat":{"id":123,"first_name":"Name","username":"username","type":"private"},"date":1581200384,"text":"This is a test message"}}"#;
         let image = Image {
             chat_id: "123",
-            photo: "image url",
+            photo: InputFile::Url("image url"),
             disable_notification: true,
+            ..Default::default()
         };
 
         let mock = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
@@ -225,7 +761,7 @@ mod tests {
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.send_photo(&image).await.unwrap();
-        assert_eq!(result.as_str(), "This is a test message");
+        assert_eq!(result.content(), "This is a test message");
         mock.assert();
     }
 
@@ -235,8 +771,9 @@ mod tests {
         let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#;
         let image = Image {
             chat_id: "123",
-            photo: "image url",
+            photo: InputFile::Url("image url"),
             disable_notification: true,
+            ..Default::default()
         };
 
         let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
@@ -248,11 +785,238 @@ mod tests {
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.send_photo(&image).await.unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert_eq!(format!("{}", result), "Bad Request: chat not found (400)");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn upload_image_success() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":{"message_id":691,"from":{"id":414141,"is_bot":true,"first_name":"Bot","username":"Bot"},"chat":{"id":123,"first_name":"Name","username":"username","type":"private"},"date":1581200384,"caption":"Chart caption"}}"#;
+        let image = Image {
+            chat_id: "123",
+            photo: InputFile::Upload {
+                file_name: "chart.png",
+                bytes: b"image bytes",
+            },
+            caption: Some("Chart caption"),
+            disable_notification: true,
+            ..Default::default()
+        };
+
+        let mock = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex(r#"name="photo"; filename="chart.png""#.to_string()),
+                Matcher::Regex("image bytes".to_string()),
+                Matcher::Regex("Chart caption".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.send_photo(&image).await.unwrap();
+        assert_eq!(result.content(), "Chart caption");
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn upload_document_success() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":{"message_id":692,"chat":{"id":123,"type":"private"},"date":1581200384,"caption":"*Expenses*","document":{"file_id":"BQAC","file_unique_id":"AgAD","file_name":"expenses.csv","mime_type":"text/csv","file_size":11}}}"#;
+        let document = Document {
+            chat_id: "123",
+            document: InputFile::Upload {
+                file_name: "expenses.csv",
+                bytes: b"90,food\n",
+            },
+            caption: Some("*Expenses*"),
+            parse_mode: Some(ParseMode::MarkdownV2),
+            ..Default::default()
+        };
+
+        let mock = mock("POST", format!("/bot{}/sendDocument", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex(r#"name="document"; filename="expenses.csv""#.to_string()),
+                Matcher::Regex("MarkdownV2".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.send_document(&document).await.unwrap();
+        assert_eq!(result.message_id, 692);
+        let sent_document = result.document.unwrap();
+        assert_eq!(sent_document.file_id, "BQAC");
+        assert_eq!(sent_document.file_name.as_deref(), Some("expenses.csv"));
+        mock.assert();
+    }
+
+    #[test]
+    fn reply_markups_are_serialized() {
+        let keyboard = ReplyMarkup::ReplyKeyboardMarkup(ReplyKeyboardMarkup {
+            keyboard: vec![vec![KeyboardButton::new("$"), KeyboardButton::new("€")]],
+            resize_keyboard: true,
+            one_time_keyboard: true,
+        });
+        let force_reply = ReplyMarkup::ForceReply(ForceReply::new(Some("90 food")));
+        let remove = ReplyMarkup::ReplyKeyboardRemove(ReplyKeyboardRemove::default());
+
+        assert_eq!(
+            json!(keyboard),
+            json!({
+                "keyboard": [[{"text": "$"}, {"text": "€"}]],
+                "resize_keyboard": true,
+                "one_time_keyboard": true,
+            })
+        );
+        assert_eq!(
+            json!(force_reply),
+            json!({"force_reply": true, "input_field_placeholder": "90 food"})
+        );
+        assert_eq!(json!(remove), json!({"remove_keyboard": true}));
+        assert_eq!(json!(ParseMode::Html), json!("HTML"));
+    }
+
+    #[tokio::test]
+    async fn set_webhook_success() {
+        let url = &server_url();
+        let mock = mock("POST", format!("/bot{}/setWebhook", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("url".to_string(), "https://bot.example.com".to_string()),
+                Matcher::UrlEncoded("secret_token".to_string(), "secret".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true,"description":"Webhook was set"}"#)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client
+            .set_webhook("https://bot.example.com", "secret")
+            .await
+            .unwrap();
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn set_webhook_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: bad webhook: HTTPS url must be provided for webhook"}"#;
+        let _m = mock("POST", format!("/bot{}/setWebhook", TOKEN).as_str())
+            .with_status(400)
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client
+            .set_webhook("http://bot.example.com", "secret")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!("{}", result),
+            "Bad Request: bad webhook: HTTPS url must be provided for webhook (400)"
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn delete_webhook_success() {
+        let url = &server_url();
+        let mock = mock("POST", format!("/bot{}/deleteWebhook", TOKEN).as_str())
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true,"description":"Webhook was deleted"}"#)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client.delete_webhook().await.unwrap();
+        mock.assert();
+    }
+
+    fn update(update_id: i64, text: &str) -> String {
+        format!(
+            r#"{{"update_id":{},"message":{{"message_id":691,"from":{{"id":414141,"is_bot":false,"first_name":"Name","username":"username"}},"chat":{{"id":414141,"first_name":"Name","username":"username","type":"private"}},"date":1581200384,"text":"{}"}}}}"#,
+            update_id, text
+        )
+    }
+
+    #[tokio::test]
+    async fn get_updates_success() {
+        let url = &server_url();
+        let resp = format!(
+            r#"{{"ok":true,"result":[{},{{"update_id":11,"callback_query":{{"id":"1","from":{{"id":414141,"is_bot":false,"first_name":"Name"}},"message":{{"message_id":692,"chat":{{"id":-100,"title":"Group","type":"group"}},"date":1581200384,"text":"Choose"}},"chat_instance":"1","data":"$"}}}}]}}"#,
+            update(10, "/start")
+        );
+        let mock = mock("POST", format!("/bot{}/getUpdates", TOKEN).as_str())
+            .match_body(Matcher::PartialJson(json!({"offset": 10, "timeout": 0})))
+            .with_status(200)
+            .with_body(resp)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let updates = client.get_updates(10, 0).await.unwrap();
+
+        assert_eq!(updates.len(), 2);
+        let message = updates[0].message.as_ref().unwrap();
+        assert_eq!(message.text.as_deref(), Some("/start"));
+        assert_eq!(message.chat.id, 414141);
+        assert_eq!(
+            message.from.as_ref().unwrap().username.as_deref(),
+            Some("username")
+        );
+        let query = updates[1].callback_query.as_ref().unwrap();
+        assert_eq!(query.data.as_deref(), Some("$"));
+        assert_eq!(query.message.as_ref().unwrap().chat.id, -100);
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn get_updates_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":401,"description":"Unauthorized"}"#;
+        let _m = mock("POST", format!("/bot{}/getUpdates", TOKEN).as_str())
+            .with_status(401)
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_updates(0, 0).await.unwrap_err();
+        assert_eq!(format!("{}", result), "Unauthorized (401)");
         _m.assert();
     }
 
+    #[tokio::test]
+    async fn stream_polls_from_moved_offset() {
+        let url = &server_url();
+        let first = mock("POST", format!("/bot{}/getUpdates", TOKEN).as_str())
+            .match_body(Matcher::PartialJson(json!({"offset": 5})))
+            .with_status(200)
+            .with_body(format!(
+                r#"{{"ok":true,"result":[{},{}]}}"#,
+                update(5, "/add"),
+                update(6, "90")
+            ))
+            .create();
+        let second = mock("POST", format!("/bot{}/getUpdates", TOKEN).as_str())
+            .match_body(Matcher::PartialJson(json!({"offset": 7})))
+            .with_status(200)
+            .with_body(format!(r#"{{"ok":true,"result":[{}]}}"#, update(7, "food")))
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let offset = Arc::new(AtomicI64::new(5));
+        let mut updates = client.stream(offset.clone());
+
+        assert_eq!(updates.next().await.unwrap().unwrap().update_id, 5);
+        assert_eq!(updates.next().await.unwrap().unwrap().update_id, 6);
+        offset.store(7, Ordering::SeqCst);
+        assert_eq!(updates.next().await.unwrap().unwrap().update_id, 7);
+        first.assert();
+        second.assert();
+    }
+
     #[tokio::test]
     async fn delete_message_success() {
         let url = &server_url();
@@ -295,8 +1059,7 @@ mod tests {
             .delete_message(chat_id, message_id)
             .await
             .unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert_eq!(format!("{}", result), "Bad Request: chat not found (400)");
         _m.assert();
     }
 
@@ -314,6 +1077,7 @@ mod tests {
             chat_id: "123",
             message_id: "456",
             text,
+            parse_mode: None,
             disable_notification: true,
             disable_web_page_preview: false,
             reply_markup: Some(&reply_markup),
@@ -322,13 +1086,14 @@ mod tests {
         let mock = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
             .match_body(Matcher::Json(json!(message)))
             .with_status(200)
-            .with_body("success")
+            .with_body(r#"{"ok":true,"result":{"message_id":456,"date":0,"chat":{"id":123,"type":"private"},"text":"message text"}}"#)
             .with_header("content-type", "application/json")
             .create();
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.edit_message_text(&message).await.unwrap();
-        assert_eq!(result, ());
+        assert_eq!(result.message_id, 456);
+        assert_eq!(result.content(), text);
         mock.assert();
     }
 
@@ -347,6 +1112,7 @@ mod tests {
             chat_id: "123",
             message_id: "456",
             text,
+            parse_mode: None,
             disable_notification: true,
             disable_web_page_preview: false,
             reply_markup: Some(&reply_markup),
@@ -361,8 +1127,33 @@ mod tests {
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.edit_message_text(&message).await.unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert_eq!(format!("{}", result), "Bad Request: chat not found (400)");
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn answer_callback_query_success() {
+        let url = &server_url();
+        let answer = AnswerCallbackQuery {
+            callback_query_id: "4382",
+            ..Default::default()
+        };
+
+        let mock = mock(
+            "POST",
+            format!("/bot{}/answerCallbackQuery", TOKEN).as_str(),
+        )
+        .match_body(Matcher::Json(json!({
+            "callback_query_id": "4382",
+            "show_alert": false,
+        })))
+        .with_status(200)
+        .with_body(r#"{"ok":true,"result":true}"#)
+        .with_header("content-type", "application/json")
+        .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client.answer_callback_query(&answer).await.unwrap();
         mock.assert();
     }
 
@@ -381,13 +1172,13 @@ mod tests {
         let mock = mock("POST", format!("/bot{}/editMessageMedia", TOKEN).as_str())
             .match_body(Matcher::Json(json!(edit_image)))
             .with_status(200)
-            .with_body("success")
+            .with_body(r#"{"ok":true,"result":{"message_id":456,"date":0,"chat":{"id":123,"type":"private"}}}"#)
             .with_header("content-type", "application/json")
             .create();
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.edit_message_image(&edit_image).await.unwrap();
-        assert_eq!(result, ());
+        assert_eq!(result.message_id, 456);
         mock.assert();
     }
 
@@ -413,8 +1204,7 @@ mod tests {
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.edit_message_image(&edit_image).await.unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert_eq!(format!("{}", result), "Bad Request: chat not found (400)");
         mock.assert();
     }
 }
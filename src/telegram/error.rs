@@ -3,15 +3,68 @@ use std::fmt;
 
 use reqwest::Error as ReqwestError;
 use serde::export::Formatter;
+use serde::Deserialize;
 use serde_json::error::Error as SerdeError;
 
 #[derive(Debug)]
 pub enum TelegramError {
     NetworkError(ReqwestError),
     MalformedResponse(SerdeError),
+    /// error response of the Bot API
+    Api(ApiError),
+    /// unsuccessful response which is not a response of the Bot API, e.g. of a gateway
     Unsuccessful(String),
 }
 
+/// Error described by the Bot API, e.g. `{"ok":false,"error_code":429,"description":"..."}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub error_code: u16,
+    pub description: String,
+    pub parameters: Option<ResponseParameters>,
+}
+
+/// Hints on how the failed request can be repeated.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ResponseParameters {
+    /// the group has been migrated to a supergroup with this id
+    pub migrate_to_chat_id: Option<i64>,
+    /// seconds to wait before the request can be repeated after flood control has been exceeded
+    pub retry_after: Option<u64>,
+}
+
+impl TelegramError {
+    /// seconds Telegram asks to wait before repeating the request, it is set for 429 responses
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            TelegramError::Api(error) => error.parameters.and_then(|params| params.retry_after),
+            _ => None,
+        }
+    }
+
+    /// whether the same request may succeed later: the network or Telegram has failed
+    /// or flood control has been exceeded. Requests rejected as invalid fail the same way again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            TelegramError::NetworkError(err) => !err.is_builder(),
+            TelegramError::MalformedResponse(_) => false,
+            TelegramError::Api(error) => error.error_code == 429 || error.error_code >= 500,
+            TelegramError::Unsuccessful(_) => true,
+        }
+    }
+
+    /// whether Telegram has surely not acted on the request: the connection has not been
+    /// established or flood control has rejected it. Otherwise e.g. a message may have been
+    /// sent even though its response has been lost.
+    pub fn is_unprocessed(&self) -> bool {
+        match self {
+            TelegramError::NetworkError(err) => err.is_connect(),
+            TelegramError::Api(error) => error.error_code == 429,
+            _ => false,
+        }
+    }
+}
+
 impl From<ReqwestError> for TelegramError {
     fn from(error: ReqwestError) -> Self {
         TelegramError::NetworkError(error)
@@ -37,6 +90,7 @@ impl fmt::Display for TelegramError {
         match self {
             TelegramError::NetworkError(err) => err.fmt(f),
             TelegramError::MalformedResponse(err) => err.fmt(f),
+            TelegramError::Api(error) => write!(f, "{} ({})", error.description, error.error_code),
             TelegramError::Unsuccessful(err) => err.fmt(f),
         }
     }
@@ -44,7 +98,7 @@ impl fmt::Display for TelegramError {
 
 #[cfg(test)]
 mod tests {
-    use crate::telegram::error::TelegramError;
+    use crate::telegram::error::{ApiError, TelegramError};
     use serde::de::Error;
     use serde_json::error::Error as SerdeError;
 
@@ -65,4 +119,41 @@ mod tests {
             "Fail due to malformed response"
         );
     }
+
+    #[test]
+    fn flood_wait_is_transient() {
+        let error: ApiError = serde_json::from_str(
+            r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 7","parameters":{"retry_after":7}}"#,
+        )
+        .unwrap();
+        let error = TelegramError::Api(error);
+
+        assert_eq!(error.retry_after(), Some(7));
+        assert!(error.is_transient());
+        assert!(error.is_unprocessed());
+        assert_eq!(
+            format!("{}", error),
+            "Too Many Requests: retry after 7 (429)"
+        );
+    }
+
+    #[test]
+    fn bad_request_is_not_transient() {
+        let error = TelegramError::Api(ApiError {
+            error_code: 400,
+            description: "Bad Request: chat not found".to_string(),
+            parameters: None,
+        });
+
+        assert_eq!(error.retry_after(), None);
+        assert!(!error.is_transient());
+    }
+
+    #[test]
+    fn gateway_error_may_have_been_processed() {
+        let error = TelegramError::Unsuccessful("Bad Gateway".to_string());
+
+        assert!(error.is_transient());
+        assert!(!error.is_unprocessed());
+    }
 }
@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::{self, BoxStream, StreamExt};
+
+use crate::telegram::client::TelegramService;
+use crate::telegram::error::TelegramError;
+use crate::telegram::types::{
+    AnswerCallbackQuery, Chat, Document, EditImage, EditMessage, Image, InputFile, Message,
+    ReceivedMessage, ReplyMarkup, Update,
+};
+
+/// Request the bot has sent to Telegram, buttons of keyboards are kept as their texts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SentRequest {
+    Message {
+        chat_id: String,
+        text: String,
+        keyboard: Vec<Vec<String>>,
+    },
+    Photo {
+        chat_id: String,
+        caption: Option<String>,
+    },
+    Document {
+        chat_id: String,
+        file_name: String,
+        caption: Option<String>,
+    },
+    EditedText {
+        chat_id: String,
+        message_id: String,
+        text: String,
+        keyboard: Vec<Vec<String>>,
+    },
+    EditedImage {
+        chat_id: String,
+        message_id: String,
+        photo: String,
+    },
+    Deleted {
+        chat_id: String,
+        message_id: String,
+    },
+    CallbackAnswer {
+        callback_query_id: String,
+        text: Option<String>,
+    },
+}
+
+/// Telegram service which records requests instead of sending them and serves the given
+/// updates, so dialogs can be tested without HTTP fixtures.
+#[derive(Debug, Default)]
+pub struct RecordingTelegramClient {
+    username: String,
+    updates: Mutex<VecDeque<Update>>,
+    last_poll: Mutex<Option<Instant>>,
+    requests: Mutex<Vec<SentRequest>>,
+    /// sending a message with this text panics
+    panic_on: Option<String>,
+}
+
+impl RecordingTelegramClient {
+    pub fn new(username: &str) -> Self {
+        RecordingTelegramClient {
+            username: username.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// updates served by polling, they are removed once the next ones are polled
+    pub fn with_updates(username: &str, updates: Vec<Update>) -> Self {
+        RecordingTelegramClient {
+            username: username.to_string(),
+            updates: Mutex::new(updates.into_iter().collect()),
+            ..Default::default()
+        }
+    }
+
+    /// sending a message with the text panics, e.g. to test that handlers are isolated
+    pub fn with_panic_on(mut self, text: &str) -> Self {
+        self.panic_on = Some(text.to_string());
+        self
+    }
+
+    /// all requests sent so far in order
+    pub fn requests(&self) -> Vec<SentRequest> {
+        lock(&self.requests).clone()
+    }
+
+    /// texts of the messages sent so far in order
+    pub fn sent_texts(&self) -> Vec<String> {
+        lock(&self.requests)
+            .iter()
+            .filter_map(|request| match request {
+                SentRequest::Message { text, .. } => Some(text.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn record(&self, request: SentRequest) {
+        lock(&self.requests).push(request);
+    }
+
+    /// records the request and returns the message Telegram would send back,
+    /// ids of the messages follow the order of requests
+    fn record_sent(
+        &self,
+        request: SentRequest,
+        chat_id: &str,
+        text: Option<&str>,
+        caption: Option<&str>,
+    ) -> ReceivedMessage {
+        let message_id = {
+            let mut requests = lock(&self.requests);
+            requests.push(request);
+            requests.len() as i64
+        };
+        received(chat_id, message_id, text, caption)
+    }
+}
+
+/// message Telegram returns for the sent or edited message
+fn received(
+    chat_id: &str,
+    message_id: i64,
+    text: Option<&str>,
+    caption: Option<&str>,
+) -> ReceivedMessage {
+    let chat_id = chat_id.parse().unwrap_or_default();
+    ReceivedMessage {
+        message_id,
+        from: None,
+        chat: Chat {
+            id: chat_id,
+            type_: if chat_id < 0 { "group" } else { "private" }.to_string(),
+        },
+        date: Utc::now().timestamp(),
+        text: text.map(str::to_string),
+        caption: caption.map(str::to_string),
+        document: None,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn keyboard(reply_markup: Option<&ReplyMarkup>) -> Vec<Vec<String>> {
+    match reply_markup {
+        Some(ReplyMarkup::InlineKeyboardMarkup(markup)) => markup
+            .inline_keyboard
+            .iter()
+            .map(|row| row.iter().map(|button| button.text.clone()).collect())
+            .collect(),
+        Some(ReplyMarkup::ReplyKeyboardMarkup(markup)) => markup
+            .keyboard
+            .iter()
+            .map(|row| row.iter().map(|button| button.text.clone()).collect())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[async_trait]
+impl TelegramService for RecordingTelegramClient {
+    fn stream(&self, offset: Arc<AtomicI64>) -> BoxStream<'_, Result<Update, TelegramError>> {
+        stream::once(self.get_updates(offset.load(Ordering::SeqCst), 0))
+            .map(|updates| match updates {
+                Ok(updates) => stream::iter(updates.into_iter().map(Ok).collect::<Vec<_>>()),
+                Err(err) => stream::iter(vec![Err(err)]),
+            })
+            .flatten()
+            .boxed()
+    }
+
+    fn last_poll(&self) -> Option<Instant> {
+        *lock(&self.last_poll)
+    }
+
+    async fn get_updates(&self, offset: i64, _timeout: u64) -> Result<Vec<Update>, TelegramError> {
+        *lock(&self.last_poll) = Some(Instant::now());
+        let mut updates = lock(&self.updates);
+        updates.retain(|update| update.update_id >= offset);
+        Ok(updates.iter().cloned().collect())
+    }
+
+    async fn get_me(&self) -> Result<String, TelegramError> {
+        Ok(self.username.clone())
+    }
+
+    async fn set_webhook(
+        &self,
+        _webhook_url: &str,
+        _secret_token: &str,
+    ) -> Result<(), TelegramError> {
+        Ok(())
+    }
+
+    async fn delete_webhook(&self) -> Result<(), TelegramError> {
+        Ok(())
+    }
+
+    async fn send_message(&self, message: &Message<'_>) -> Result<ReceivedMessage, TelegramError> {
+        if self.panic_on.as_deref() == Some(message.text) {
+            panic!("sending of {:?} has panicked", message.text);
+        }
+        let request = SentRequest::Message {
+            chat_id: message.chat_id.to_string(),
+            text: message.text.to_string(),
+            keyboard: keyboard(message.reply_markup),
+        };
+        Ok(self.record_sent(request, message.chat_id, Some(message.text), None))
+    }
+
+    async fn send_photo(&self, image: &Image<'_>) -> Result<ReceivedMessage, TelegramError> {
+        let request = SentRequest::Photo {
+            chat_id: image.chat_id.to_string(),
+            caption: image.caption.map(str::to_string),
+        };
+        Ok(self.record_sent(request, image.chat_id, None, image.caption))
+    }
+
+    async fn send_document(
+        &self,
+        document: &Document<'_>,
+    ) -> Result<ReceivedMessage, TelegramError> {
+        let file_name = match document.document {
+            InputFile::Url(url) => url,
+            InputFile::Upload { file_name, .. } => file_name,
+        };
+        let request = SentRequest::Document {
+            chat_id: document.chat_id.to_string(),
+            file_name: file_name.to_string(),
+            caption: document.caption.map(str::to_string),
+        };
+        Ok(self.record_sent(request, document.chat_id, None, document.caption))
+    }
+
+    async fn delete_message(&self, chat_id: &str, message_id: &str) -> Result<(), TelegramError> {
+        self.record(SentRequest::Deleted {
+            chat_id: chat_id.to_string(),
+            message_id: message_id.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn edit_message_text(
+        &self,
+        message: &EditMessage<'_>,
+    ) -> Result<ReceivedMessage, TelegramError> {
+        self.record(SentRequest::EditedText {
+            chat_id: message.chat_id.to_string(),
+            message_id: message.message_id.to_string(),
+            text: message.text.to_string(),
+            keyboard: keyboard(message.reply_markup),
+        });
+        Ok(received(
+            message.chat_id,
+            message.message_id.parse().unwrap_or_default(),
+            Some(message.text),
+            None,
+        ))
+    }
+
+    async fn edit_message_image(
+        &self,
+        edit_image: &EditImage<'_>,
+    ) -> Result<ReceivedMessage, TelegramError> {
+        self.record(SentRequest::EditedImage {
+            chat_id: edit_image.chat_id.to_string(),
+            message_id: edit_image.message_id.to_string(),
+            photo: edit_image.photo.to_string(),
+        });
+        Ok(received(
+            edit_image.chat_id,
+            edit_image.message_id.parse().unwrap_or_default(),
+            None,
+            None,
+        ))
+    }
+
+    async fn answer_callback_query(
+        &self,
+        answer: &AnswerCallbackQuery<'_>,
+    ) -> Result<(), TelegramError> {
+        self.record(SentRequest::CallbackAnswer {
+            callback_query_id: answer.callback_query_id.to_string(),
+            text: answer.text.map(str::to_string),
+        });
+        Ok(())
+    }
+}
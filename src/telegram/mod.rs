@@ -1,6 +1,10 @@
 pub mod client;
 pub mod error;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fake;
+pub mod rate_limit;
 pub mod types;
+pub mod webhook;
 
 #[cfg(test)]
 pub mod test_helpers;
@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Messages sent to all chats per second, Telegram starts to reject them above it.
+const GLOBAL_PER_SECOND: f64 = 30.0;
+
+/// Messages sent to a private chat per second.
+const PRIVATE_CHAT_PER_SECOND: f64 = 1.0;
+
+/// Messages sent to a group per second, Telegram allows 20 per minute.
+const GROUP_PER_SECOND: f64 = 20.0 / 60.0;
+
+/// Shortest pause before a waiting sender checks the limits again.
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Buckets of idle chats are dropped once there are more of them.
+const MAX_CHAT_BUCKETS: usize = 1024;
+
+/// Waiting senders are served in order of arrival.
+type Ticket = u64;
+
+/// Keeps outgoing messages within the limits of Telegram by token buckets: one for all chats
+/// and one per chat. Senders wait for tokens in a queue.
+#[derive(Debug)]
+pub struct RateLimiter {
+    private_chat_per_second: f64,
+    group_per_second: f64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    global: TokenBucket,
+    chats: HashMap<String, TokenBucket>,
+    waiting: BTreeMap<Ticket, String>,
+    last_ticket: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    per_second: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// full bucket, a burst of up to `capacity` tokens can be taken at once
+    fn new(per_second: f64, capacity: f64, now: Instant) -> Self {
+        TokenBucket {
+            per_second,
+            capacity,
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    fn tokens(&self, now: Instant) -> f64 {
+        let refilled = now.saturating_duration_since(self.updated).as_secs_f64() * self.per_second;
+        (self.tokens + refilled).min(self.capacity)
+    }
+
+    /// time until a token can be taken
+    fn wait_time(&self, now: Instant) -> Duration {
+        let missing = 1.0 - self.tokens(now);
+        if missing <= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(missing / self.per_second)
+        }
+    }
+
+    fn take(&mut self, now: Instant) {
+        self.tokens = self.tokens(now) - 1.0;
+        self.updated = now;
+    }
+}
+
+impl RateLimiter {
+    /// limiter allowing the given number of messages per second to all chats, to a private
+    /// chat and to a group
+    pub fn new(
+        global_per_second: f64,
+        private_chat_per_second: f64,
+        group_per_second: f64,
+    ) -> Self {
+        RateLimiter {
+            private_chat_per_second,
+            group_per_second,
+            state: Mutex::new(State {
+                global: TokenBucket::new(
+                    global_per_second,
+                    global_per_second.max(1.0),
+                    Instant::now(),
+                ),
+                chats: HashMap::new(),
+                waiting: BTreeMap::new(),
+                last_ticket: 0,
+            }),
+        }
+    }
+
+    /// waits until a message can be sent to the chat, earlier messages are let through first
+    pub async fn acquire(&self, chat_id: &str) {
+        let waiting = Waiting::enqueue(self, chat_id);
+        loop {
+            let wait = match self.try_acquire(waiting.ticket, chat_id, Instant::now()) {
+                Some(wait) => wait,
+                None => return,
+            };
+            tokio::time::delay_for(wait.max(MIN_CHECK_INTERVAL)).await;
+        }
+    }
+
+    /// takes tokens when it is the turn of the ticket, otherwise returns how long to wait
+    fn try_acquire(&self, ticket: Ticket, chat_id: &str, now: Instant) -> Option<Duration> {
+        let mut state = self.lock();
+        // chats which have just received a message do not hold up the others
+        let next = state
+            .waiting
+            .iter()
+            .find(|(_, chat)| {
+                state
+                    .chats
+                    .get(chat.as_str())
+                    .map_or(true, |bucket| bucket.tokens(now) >= 1.0)
+            })
+            .map(|(ticket, _)| *ticket);
+        let global_wait = state.global.wait_time(now);
+        if next == Some(ticket) && global_wait == Duration::from_secs(0) {
+            state.global.take(now);
+            let chat_per_second = self.chat_per_second(chat_id);
+            state
+                .chats
+                .entry(chat_id.to_string())
+                // messages to a chat are spaced evenly, Telegram rejects bursts to a chat
+                .or_insert_with(|| TokenBucket::new(chat_per_second, 1.0, now))
+                .take(now);
+            if state.chats.len() > MAX_CHAT_BUCKETS {
+                state
+                    .chats
+                    .retain(|_, bucket| bucket.tokens(now) < bucket.capacity);
+            }
+            return None;
+        }
+        let chat_wait = state
+            .chats
+            .get(chat_id)
+            .map_or(Duration::from_secs(0), |bucket| bucket.wait_time(now));
+        Some(global_wait.max(chat_wait))
+    }
+
+    fn chat_per_second(&self, chat_id: &str) -> f64 {
+        if chat_id.starts_with('-') {
+            self.group_per_second
+        } else {
+            self.private_chat_per_second
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(GLOBAL_PER_SECOND, PRIVATE_CHAT_PER_SECOND, GROUP_PER_SECOND)
+    }
+}
+
+/// Place of a sender in the queue, it is left when the sender stops waiting for any reason.
+struct Waiting<'a> {
+    limiter: &'a RateLimiter,
+    ticket: Ticket,
+}
+
+impl<'a> Waiting<'a> {
+    fn enqueue(limiter: &'a RateLimiter, chat_id: &str) -> Self {
+        let mut state = limiter.lock();
+        state.last_ticket += 1;
+        let ticket = state.last_ticket;
+        let _ = state.waiting.insert(ticket, chat_id.to_string());
+        Waiting { limiter, ticket }
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let _ = self.limiter.lock().waiting.remove(&self.ticket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn messages_to_chat_are_spaced() {
+        tokio::time::pause();
+        let limiter = RateLimiter::new(100.0, 20.0, 20.0);
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            limiter.acquire("414141").await;
+        }
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn busy_chat_does_not_hold_up_others() {
+        tokio::time::pause();
+        let limiter = RateLimiter::new(100.0, 1.0, 1.0);
+        limiter.acquire("414141").await;
+        let started = Instant::now();
+
+        limiter.acquire("424242").await;
+
+        assert_eq!(started.elapsed(), Duration::from_secs(0));
+    }
+
+    #[tokio::test]
+    async fn messages_wait_for_global_limit_in_order() {
+        tokio::time::pause();
+        let limiter = Arc::new(RateLimiter::new(10.0, 100.0, 100.0));
+        // empties the global bucket, so the next messages have to wait
+        for chat in 0..10 {
+            limiter.acquire(&chat.to_string()).await;
+        }
+        let sent = Arc::new(Mutex::new(vec![]));
+        let send = |chat_id: &'static str| {
+            let limiter = limiter.clone();
+            let sent = sent.clone();
+            tokio::spawn(async move {
+                limiter.acquire(chat_id).await;
+                sent.lock().unwrap().push(chat_id);
+            })
+        };
+
+        let first = send("1001");
+        tokio::time::advance(Duration::from_millis(10)).await;
+        let second = send("1002");
+        let _ = first.await;
+        let _ = second.await;
+
+        assert_eq!(*sent.lock().unwrap(), vec!["1001", "1002"]);
+    }
+}
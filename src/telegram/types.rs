@@ -1,11 +1,13 @@
-use std::fmt::Result;
+use std::result;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize, Serializer};
 
 #[derive(Serialize, Debug, Default)]
 pub struct Message<'a> {
     pub chat_id: &'a str,
     pub text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_mode: Option<ParseMode>,
     pub disable_notification: bool,
     pub disable_web_page_preview: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -17,25 +19,31 @@ pub struct EditMessage<'a> {
     pub chat_id: &'a str,
     pub message_id: &'a str,
     pub text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_mode: Option<ParseMode>,
     pub disable_notification: bool,
     pub disable_web_page_preview: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reply_markup: Option<&'a ReplyMarkup>,
 }
 
-#[derive(Serialize)]
+/// How Telegram formats entities in the text, e.g. `*bold*` in MarkdownV2.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    MarkdownV2,
+    #[serde(rename = "HTML")]
+    Html,
+    /// legacy Markdown, kept by Telegram for backward compatibility
+    Markdown,
+}
+
+#[derive(Serialize, Debug)]
 #[serde(untagged)]
-#[allow(dead_code)]
 pub enum ReplyMarkup {
     InlineKeyboardMarkup(InlineKeyboardMarkup),
-}
-
-impl std::fmt::Debug for ReplyMarkup {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result {
-        f.debug_struct("ReplyMarkup")
-            .field("InlineKeyboardMarkup", &"foo")
-            .finish()
-    }
+    ReplyKeyboardMarkup(ReplyKeyboardMarkup),
+    ReplyKeyboardRemove(ReplyKeyboardRemove),
+    ForceReply(ForceReply),
 }
 
 #[derive(Serialize, Debug, Default, Clone)]
@@ -54,19 +62,113 @@ impl InlineKeyboardButton {
     }
 }
 
-#[allow(dead_code)]
 #[derive(Serialize, Debug, Default)]
 pub struct InlineKeyboardMarkup {
     pub inline_keyboard: Vec<Vec<InlineKeyboardButton>>,
 }
 
+/// Keyboard shown instead of the letters, a pressed button sends its text as a message.
+#[derive(Serialize, Debug, Default)]
+pub struct ReplyKeyboardMarkup {
+    pub keyboard: Vec<Vec<KeyboardButton>>,
+    /// fits the keyboard to the buttons instead of the height of the letters
+    pub resize_keyboard: bool,
+    /// hides the keyboard once a button is pressed
+    pub one_time_keyboard: bool,
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct KeyboardButton {
+    pub text: String,
+}
+
+impl KeyboardButton {
+    pub fn new(text: &str) -> Self {
+        KeyboardButton {
+            text: text.to_string(),
+        }
+    }
+}
+
+/// Hides the reply keyboard and shows the letters again.
+#[derive(Serialize, Debug)]
+pub struct ReplyKeyboardRemove {
+    remove_keyboard: bool,
+}
+
+impl Default for ReplyKeyboardRemove {
+    fn default() -> Self {
+        ReplyKeyboardRemove {
+            remove_keyboard: true,
+        }
+    }
+}
+
+/// Makes the app of the user reply to the message, e.g. when the bot asks for an amount.
+#[derive(Serialize, Debug)]
+pub struct ForceReply {
+    force_reply: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_field_placeholder: Option<String>,
+}
+
+impl ForceReply {
+    /// placeholder is shown in the empty input field, up to 64 characters
+    pub fn new(input_field_placeholder: Option<&str>) -> Self {
+        ForceReply {
+            force_reply: true,
+            input_field_placeholder: input_field_placeholder.map(str::to_string),
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Default)]
 pub struct Image<'a> {
     pub chat_id: &'a str,
-    pub photo: &'a str,
+    pub photo: InputFile<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_mode: Option<ParseMode>,
+    pub disable_notification: bool,
+}
+
+/// General file sent by sendDocument, e.g. an export of expenses.
+#[derive(Serialize, Debug, Default)]
+pub struct Document<'a> {
+    pub chat_id: &'a str,
+    pub document: InputFile<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_mode: Option<ParseMode>,
     pub disable_notification: bool,
 }
 
+/// File sent to Telegram, either by URL or uploaded along with the request.
+#[derive(Debug)]
+pub enum InputFile<'a> {
+    Url(&'a str),
+    Upload { file_name: &'a str, bytes: &'a [u8] },
+}
+
+impl Default for InputFile<'_> {
+    fn default() -> Self {
+        InputFile::Url("")
+    }
+}
+
+impl Serialize for InputFile<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> result::Result<S::Ok, S::Error> {
+        match self {
+            InputFile::Url(url) => serializer.serialize_str(url),
+            InputFile::Upload { file_name, .. } => {
+                serializer.serialize_str(&format!("attach://{}", file_name))
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Default)]
 pub struct EditImage<'a> {
     pub chat_id: &'a str,
@@ -81,3 +183,89 @@ pub struct Media<'a> {
     #[serde(rename = "type")]
     pub type_: &'a str,
 }
+
+/// Incoming update, only messages and callback queries are processed by the bot.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Update {
+    pub update_id: i64,
+    pub message: Option<ReceivedMessage>,
+    pub callback_query: Option<CallbackQuery>,
+}
+
+/// Message received in an update or sent by the bot, its id is needed to edit it later.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ReceivedMessage {
+    pub message_id: i64,
+    pub from: Option<User>,
+    pub chat: Chat,
+    /// unix time the message was sent at
+    pub date: i64,
+    pub text: Option<String>,
+    pub caption: Option<String>,
+    pub document: Option<ReceivedDocument>,
+}
+
+impl ReceivedMessage {
+    /// text of the message or caption of its media, empty when there is neither
+    pub fn content(&self) -> String {
+        self.text
+            .as_ref()
+            .or_else(|| self.caption.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// File attached to a message, it can be sent again by its id.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ReceivedDocument {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub id: i64,
+    pub is_bot: bool,
+    pub first_name: String,
+    pub username: Option<String>,
+    /// IETF language tag of the user's Telegram app, replies are localized by it
+    pub language_code: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Chat {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
+/// Button of an inline keyboard pressed by the user, it has to be answered.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CallbackQuery {
+    pub id: String,
+    pub from: User,
+    /// message with the keyboard, it is missing when the message is too old
+    pub message: Option<ReceivedMessage>,
+    pub data: Option<String>,
+}
+
+/// Answer to a callback query, it stops the progress indicator on the pressed button.
+#[derive(Serialize, Debug, Default)]
+pub struct AnswerCallbackQuery<'a> {
+    pub callback_query_id: &'a str,
+    /// notification shown to the user, nothing is shown when it is not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<&'a str>,
+    /// shows the text as an alert instead of a notification at the top of the chat
+    pub show_alert: bool,
+}
+
+/// Body of a successful response of the Bot API.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Response<T> {
+    pub result: T,
+}
@@ -0,0 +1,189 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+
+use hyper::header::HeaderMap;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::telegram::types::Update;
+
+/// Header Telegram sends the secret token in, the token is set along with the webhook.
+pub const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+/// Where Telegram delivers updates to and how the bot receives them.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// public url of the bot, Telegram sends updates to it
+    pub url: String,
+    /// address the embedded HTTP server listens on
+    pub address: SocketAddr,
+    pub secret_token: String,
+}
+
+/// receives updates sent by Telegram and passes them to the sender until shutdown,
+/// requests being handled at that moment are completed
+pub async fn listen<F>(
+    address: SocketAddr,
+    secret_token: String,
+    sender: UnboundedSender<Update>,
+    shutdown: F,
+) -> Result<(), hyper::Error>
+where
+    F: Future<Output = ()>,
+{
+    let make_service = make_service_fn(move |_| {
+        let secret_token = secret_token.clone();
+        let sender = sender.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, secret_token.clone(), sender.clone())
+            }))
+        }
+    });
+
+    // binding is tried, so a taken address is returned as an error instead of a panic
+    let server = Server::try_bind(&address)?;
+    info!("listening for webhook updates on {}", address);
+    server
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    secret_token: String,
+    sender: UnboundedSender<Update>,
+) -> Result<Response<Body>, hyper::Error> {
+    if request.method() != Method::POST {
+        return Ok(empty_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    if !is_authorized(request.headers(), &secret_token) {
+        error!("webhook request with invalid secret token");
+        return Ok(empty_response(StatusCode::UNAUTHORIZED));
+    }
+
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    match serde_json::from_slice::<Update>(&body) {
+        Ok(update) => {
+            if sender.send(update).is_err() {
+                error!("webhook update can not be processed, bot is stopped");
+                return Ok(empty_response(StatusCode::SERVICE_UNAVAILABLE));
+            }
+        }
+        // Telegram keeps resending updates which are not acknowledged, so they are skipped
+        Err(err) => error!("failed to parse webhook update: {}", err),
+    }
+    Ok(empty_response(StatusCode::OK))
+}
+
+/// compares the secret token in constant time, so it can not be guessed by timing responses
+fn is_authorized(headers: &HeaderMap, secret_token: &str) -> bool {
+    match headers.get(SECRET_TOKEN_HEADER) {
+        Some(token) => {
+            let token = token.as_bytes();
+            token.len() == secret_token.len()
+                && token
+                    .iter()
+                    .zip(secret_token.as_bytes())
+                    .fold(0, |diff, (a, b)| diff | (a ^ b))
+                    == 0
+        }
+        None => false,
+    }
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    const SECRET_TOKEN: &str = "secret";
+    const UPDATE: &str = r#"{"update_id":1,"message":{"message_id":691,"from":{"id":414141,"is_bot":false,"first_name":"Name"},"chat":{"id":414141,"first_name":"Name","type":"private"},"date":1581200384,"text":"/start"}}"#;
+
+    fn request(secret_token: Option<&str>, body: &str) -> Request<Body> {
+        let mut request = Request::builder().method(Method::POST).uri("/");
+        if let Some(secret_token) = secret_token {
+            request = request.header(SECRET_TOKEN_HEADER, secret_token);
+        }
+        request.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn update_is_passed_to_sender() {
+        let (sender, mut receiver) = unbounded_channel();
+
+        let response = handle_request(
+            request(Some(SECRET_TOKEN), UPDATE),
+            SECRET_TOKEN.to_string(),
+            sender,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(receiver.recv().await.unwrap().update_id, 1);
+    }
+
+    #[tokio::test]
+    async fn request_with_invalid_secret_token_is_rejected() {
+        let (sender, mut receiver) = unbounded_channel();
+
+        for secret_token in &[None, Some("wrong"), Some("secret2")] {
+            let response = handle_request(
+                request(*secret_token, UPDATE),
+                SECRET_TOKEN.to_string(),
+                sender.clone(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        drop(sender);
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn taken_address_is_an_error() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let (sender, mut receiver) = unbounded_channel();
+
+        let result = listen(
+            taken.local_addr().unwrap(),
+            SECRET_TOKEN.to_string(),
+            sender,
+            futures::future::pending(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn malformed_update_is_acknowledged() {
+        let (sender, mut receiver) = unbounded_channel();
+
+        let response = handle_request(
+            request(Some(SECRET_TOKEN), "not an update"),
+            SECRET_TOKEN.to_string(),
+            sender,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(receiver.recv().await.is_none());
+    }
+}
@@ -1,78 +1,79 @@
-use std::env;
-
-use bot::db::Connection;
+use std::sync::Arc;
 
 use bot::bot::Bot;
-use bot::db::{clear_tables, DbConnectionPool};
+use bot::db::memory::InMemoryStore;
+use bot::db::store::UserStore;
+use bot::telegram::fake::RecordingTelegramClient;
+
+const USER_ID: &str = "54981987";
 
 #[tokio::test]
 async fn full_commands_integration_flow() {
-    let bot = configure_bot();
-    let user_id = env::var("USER_ID").expect("Set USER_ID environment variable");
+    let (bot, store) = configure_bot();
 
     let response_for_help_message = bot
-        .handle_message("/help".to_string(), &user_id)
+        .handle_message("/help".to_string(), USER_ID, USER_ID)
         .await
-        .unwrap();
+        .unwrap()
+        .unwrap()
+        .content();
     let help_message = r#"You can send me these commands:
 /start
 /feedback
 /help
 /history
 /add
+/share
+/join
+/leave
+/settle
+/chart
 
 If you encounter any issues feel free to open an issue.
-Or you can also send feedback via /feedback command."#
-        .to_string();
-    assert_eq!(help_message, response_for_help_message);
+Or you can also send feedback via /feedback command."#;
+    assert_eq!(help_message, response_for_help_message.trim());
 
     let response = bot
-        .handle_message("/start".to_string(), &user_id)
+        .handle_message("/start".to_string(), USER_ID, USER_ID)
         .await
-        .unwrap();
+        .unwrap()
+        .unwrap()
+        .content();
     assert_eq!("Choose your currency".to_string(), response);
-    let response = bot.handle_message("€".to_string(), &user_id).await.unwrap();
+    let response = bot
+        .handle_message("€".to_string(), USER_ID, USER_ID)
+        .await
+        .unwrap()
+        .unwrap()
+        .content();
     assert_eq!("Your currency is €".to_string(), response);
+    let user = store.get_user(USER_ID).await.unwrap();
+    assert_eq!(user.currency, Some("€".to_string()));
 
     let response = bot
-        .handle_message("/feedback".to_string(), &user_id)
+        .handle_message("/feedback".to_string(), USER_ID, USER_ID)
         .await
-        .unwrap();
+        .unwrap()
+        .unwrap()
+        .content();
 
     assert_eq!("You can write your feedback. If you want the author to get back to you, leave your email. Or you can contact the author via telegram: @privalou Übermensch appoach is creating issue at github.com/privalou/bot".to_string(), response);
 
     let response = bot
-        .handle_message("Fooo".to_string(), &user_id)
+        .handle_message("Fooo".to_string(), USER_ID, USER_ID)
         .await
-        .unwrap();
+        .unwrap()
+        .unwrap()
+        .content();
 
     assert_eq!(
         "Thanks, 54981987, for you priceless feedback!".to_string(),
         response
     );
-
-    clean_up();
-}
-
-fn configure_bot() -> Bot {
-    dotenv::from_filename("test.env").expect("Failed to read env variables from test.env");
-    clean_up();
-    Bot::new(
-        &env::var("TELEGRAM_BOT_TOKEN").expect("Set TELEGRAM_BOT_TOKEN environment variable"),
-        &env::var("DATABASE_URL")
-            .expect("Set DATABASE_URL environment variable or configure it at test.env file"),
-    )
-}
-
-pub fn clean_up() {
-    let connection = establish_connection();
-    clear_tables(&connection);
 }
 
-pub fn establish_connection() -> Connection {
-    dotenv::from_filename("test.env").expect("Failed to read env variables from test.env");
-    let db_url = env::var("DATABASE_URL")
-        .expect("Set DATABASE_URL environment variable or configure it at test.env file");
-    let pool = DbConnectionPool::new(&db_url);
-    pool.establish_connection()
+fn configure_bot() -> (Bot<RecordingTelegramClient>, Arc<InMemoryStore>) {
+    let store = Arc::new(InMemoryStore::new());
+    let bot = Bot::with_store(RecordingTelegramClient::new("expenses_bot"), store.clone());
+    (bot, store)
 }
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use mockito::{mock, server_url, Matcher};
+use serde_json::json;
+
+use bot::bot::Bot;
+use bot::db::memory::InMemoryStore;
+use bot::db::store::{DialogStore, HistoryStore, UserStore};
+use bot::telegram::client::TelegramClient;
+
+const TOKEN: &str = "memory_store_token";
+const USER_ID: &str = "414141";
+
+#[tokio::test]
+async fn expense_is_added_without_database() {
+    let _mock = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+        .match_body(Matcher::PartialJson(json!({ "chat_id": USER_ID })))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"ok":true,"result":{"message_id":1,"date":0,"chat":{"id":414141,"type":"private"},"text":"sent"}}"#)
+        .expect_at_least(4)
+        .create();
+    let store = Arc::new(InMemoryStore::new());
+    let bot = Bot::with_store(
+        TelegramClient::new_with(TOKEN.to_string(), server_url()),
+        store.clone(),
+    );
+
+    for text in &["/start", "€", "/add", "90"] {
+        let response = bot
+            .handle_message(text.to_string(), USER_ID, USER_ID)
+            .await
+            .unwrap()
+            .unwrap()
+            .content();
+        assert_eq!(response, "sent");
+    }
+
+    let user = store.get_user(USER_ID).await.unwrap();
+    assert_eq!(user.currency, Some("€".to_string()));
+    let dialog = store.get_user_dialog(USER_ID, USER_ID).await.unwrap();
+    assert_eq!(dialog.step, Some("Category".to_string()));
+    let records = store.get_ledger_records(USER_ID).await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].amount, 90.0);
+}
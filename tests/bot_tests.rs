@@ -0,0 +1,4 @@
+mod bot {
+    mod api_integration_test;
+    mod memory_store_test;
+}
@@ -1,7 +1,9 @@
 use bot::bot::Bot;
+use bot::db::memory::InMemoryStore;
+use bot::telegram::fake::RecordingTelegramClient;
 use cucumber_rust::{async_trait, Context, Cucumber, World};
 use std::convert::Infallible;
-use std::env;
+use std::sync::Arc;
 
 pub enum BotWorld {
     Nothing,
@@ -19,6 +21,7 @@ impl World for BotWorld {
 
 mod addition_steps {
     use bot::bot::Bot;
+    use bot::telegram::fake::RecordingTelegramClient;
     use cucumber_rust::{t, Steps};
 
     use crate::BotWorld;
@@ -31,14 +34,17 @@ mod addition_steps {
         steps.when_regex_async(
             r#"^user sends "(.*)" to bot$"#,
             t!(|world, ctx| {
-                let bot = ctx.get::<Bot>().unwrap();
+                let bot = ctx.get::<Bot<RecordingTelegramClient>>().unwrap();
                 let command = &ctx.matches[1];
                 assert_eq!(command, "/help");
                 let response = bot
-                    .handle_message(command.to_string(), "54981987")
+                    .handle_message(command.to_string(), "54981987", "54981987")
                     .await
-                    .unwrap();
-                BotWorld::Response(response)
+                    .unwrap()
+                    .unwrap()
+                    .content();
+                // Telegram trims the text, the recording client keeps it as it is sent
+                BotWorld::Response(response.trim().to_string())
             }),
         );
 
@@ -69,11 +75,11 @@ async fn main() {
         .await
 }
 
-fn configure_bot() -> Bot {
-    dotenv::from_filename("test.env").expect("Failed to read env variables from test.env");
-    Bot::new(
-        &env::var("TELEGRAM_BOT_TOKEN").expect("Set TELEGRAM_BOT_TOKEN environment variable"),
-        &env::var("DATABASE_URL")
-            .expect("Set DATABASE_URL environment variable or configure it at test.env file"),
+/// bot keeping its data in memory and recording requests to Telegram, so scenarios run
+/// without a database and a bot token
+fn configure_bot() -> Bot<RecordingTelegramClient> {
+    Bot::with_store(
+        RecordingTelegramClient::new("expenses_bot"),
+        Arc::new(InMemoryStore::new()),
     )
 }
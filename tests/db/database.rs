@@ -0,0 +1,17 @@
+use bot::db::database::Database;
+use bot::db::error::DbError;
+use bot::db::store::UserStore;
+use diesel::result::Error;
+
+use crate::db::model::test_helper::establish_pool;
+
+#[tokio::test]
+async fn database_integration_test() {
+    let db = Database::new(establish_pool());
+
+    assert!(!db.is_registered("unknown_user_id").await.unwrap());
+    match db.get_user("unknown_user_id").await {
+        Err(DbError::Query(Error::NotFound)) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
@@ -12,18 +12,31 @@ const USER_ID: &str = "user_id";
 fn dialog_integration_test() {
     let conn = establish_connection();
     conn.test_transaction::<_, Error, _>(|| {
-        let result = DialogEntity::get_user_dialog(USER_ID, &conn);
+        let result = DialogEntity::get_user_dialog(USER_ID, USER_ID, &conn);
         assert!(result.is_err());
         UserEntity::save_user(USER_ID, &conn).unwrap();
-        let dialog_option = DialogEntity::get_user_dialog(USER_ID, &conn).unwrap();
+        let dialog_option = DialogEntity::get_user_dialog(USER_ID, USER_ID, &conn).unwrap();
         assert_eq!(
             DialogEntity::new(
+                USER_ID.to_string(),
                 USER_ID.to_string(),
                 Command::Start.to_string(),
                 Some("CurrencySelection".to_string()),
             ),
             dialog_option
         );
+        DialogEntity::set_keyboard_message(USER_ID, USER_ID, 7, &conn).unwrap();
+        assert_eq!(
+            Some(7),
+            DialogEntity::get_user_dialog(USER_ID, USER_ID, &conn)
+                .unwrap()
+                .keyboard_message_id
+        );
+        assert_eq!(
+            1,
+            DialogEntity::delete_dialog(USER_ID, USER_ID, &conn).unwrap()
+        );
+        assert!(DialogEntity::get_user_dialog(USER_ID, USER_ID, &conn).is_err());
         Ok(())
     });
 }
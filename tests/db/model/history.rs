@@ -10,7 +10,46 @@ fn users_integration_test() {
     let conn = establish_connection();
     conn.test_transaction::<_, Error, _>(|| {
         UserEntity::save_user("user_id", &conn).unwrap();
-        HistoryRepository::add_expense_record("user_id".to_string(), 123.00, &conn).unwrap();
+        HistoryRepository::add_expense_record(
+            "user_id".to_string(),
+            "user_id".to_string(),
+            123.00,
+            &conn,
+        )
+        .unwrap();
+        Ok(())
+    });
+}
+
+#[test]
+fn ledger_records_integration_test() {
+    let conn = establish_connection();
+    conn.test_transaction::<_, Error, _>(|| {
+        UserEntity::save_user("owner_id", &conn).unwrap();
+        UserEntity::save_user("member_id", &conn).unwrap();
+        HistoryRepository::add_expense_record(
+            "owner_id".to_string(),
+            "owner_id".to_string(),
+            10.00,
+            &conn,
+        )
+        .unwrap();
+        HistoryRepository::add_expense_record(
+            "member_id".to_string(),
+            "owner_id".to_string(),
+            20.00,
+            &conn,
+        )
+        .unwrap();
+        HistoryRepository::add_expense_record(
+            "member_id".to_string(),
+            "member_id".to_string(),
+            30.00,
+            &conn,
+        )
+        .unwrap();
+        let records = HistoryRepository::get_ledger_records("owner_id", &conn).unwrap();
+        assert_eq!(2, records.len());
         Ok(())
     });
 }
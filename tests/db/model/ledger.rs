@@ -0,0 +1,31 @@
+use diesel::result::Error;
+use diesel::Connection;
+
+use crate::db::model::test_helper::establish_connection;
+use bot::db::models::ledger::LedgerEntity;
+use bot::db::models::user::UserEntity;
+
+#[test]
+fn ledger_integration_test() {
+    let conn = establish_connection();
+    conn.test_transaction::<_, Error, _>(|| {
+        UserEntity::save_user("owner_id", &conn).unwrap();
+        UserEntity::save_user("member_id", &conn).unwrap();
+        let ledger = LedgerEntity::get_or_create("owner_id", &conn).unwrap();
+        assert_eq!(
+            ledger,
+            LedgerEntity::get_or_create("owner_id", &conn).unwrap()
+        );
+        let found =
+            LedgerEntity::find_by_invite_code(&ledger.invite_code.to_lowercase(), &conn).unwrap();
+        assert_eq!(ledger, found);
+
+        assert_eq!(
+            1,
+            UserEntity::update_ledger(Some(&ledger.id), "member_id", &conn).unwrap()
+        );
+        let member = UserEntity::get_user("member_id", &conn).unwrap();
+        assert_eq!("owner_id", member.ledger());
+        Ok(())
+    });
+}
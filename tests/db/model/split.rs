@@ -0,0 +1,43 @@
+use diesel::result::Error;
+use diesel::Connection;
+
+use crate::db::model::test_helper::establish_connection;
+use bot::db::models::history::{HistoryRepository, NewHistoryRecord};
+use bot::db::models::split::SplitRepository;
+use bot::db::models::user::UserEntity;
+
+#[test]
+fn split_integration_test() {
+    let conn = establish_connection();
+    conn.test_transaction::<_, Error, _>(|| {
+        UserEntity::save_user("payer_id", &conn).unwrap();
+        UserEntity::save_user("member_id", &conn).unwrap();
+        UserEntity::update_names(Some("Member"), "Name", "member_id", &conn).unwrap();
+        assert!(UserEntity::find_ledger_member("payer_id", "member", &conn).is_err());
+        UserEntity::update_ledger(Some("payer_id"), "member_id", &conn).unwrap();
+        let member = UserEntity::find_ledger_member("payer_id", "member", &conn).unwrap();
+        assert_eq!("member_id", member.id);
+
+        HistoryRepository::add_expense_record_with_splits(
+            NewHistoryRecord::new(
+                "payer_id".to_string(),
+                "payer_id".to_string(),
+                90.0,
+                Some("dinner".to_string()),
+            ),
+            &[
+                ("payer_id".to_string(), 45.0),
+                ("member_id".to_string(), 45.0),
+            ],
+            &conn,
+        )
+        .unwrap();
+
+        let debts = SplitRepository::get_ledger_debts("payer_id", &conn).unwrap();
+        assert_eq!(
+            vec![("payer_id".to_string(), "member_id".to_string(), 45.0)],
+            debts
+        );
+        Ok(())
+    });
+}
@@ -2,10 +2,15 @@ use std::env;
 
 use bot::db::{Connection, DbConnectionPool};
 
-pub fn establish_connection() -> Connection {
+pub fn establish_pool() -> DbConnectionPool {
     dotenv::from_filename("test.env").expect("Failed to read env variables from test.env");
     let db_url = env::var("DATABASE_URL")
         .expect("Set DATABASE_URL environment variable or configure it at test.env file");
-    let pool = DbConnectionPool::new(&db_url);
-    pool.establish_connection()
+    DbConnectionPool::new(&db_url)
+}
+
+pub fn establish_connection() -> Connection {
+    establish_pool()
+        .establish_connection()
+        .expect("Failed to get connection from pool")
 }
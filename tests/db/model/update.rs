@@ -0,0 +1,31 @@
+use diesel::result::Error;
+use diesel::Connection;
+
+use crate::db::model::test_helper::establish_connection;
+use bot::db::clear_tables;
+use bot::db::models::update::UpdateRepository;
+
+#[test]
+fn update_integration_test() {
+    let conn = establish_connection();
+    conn.test_transaction::<_, Error, _>(|| {
+        let _ = clear_tables(&conn);
+        assert_eq!(None, UpdateRepository::next_update_id(&conn).unwrap());
+
+        assert!(UpdateRepository::claim(10, &conn).unwrap());
+        assert!(UpdateRepository::claim(12, &conn).unwrap());
+        assert!(!UpdateRepository::claim(10, &conn).unwrap());
+
+        UpdateRepository::confirm(11, &conn).unwrap();
+        assert_eq!(Some(11), UpdateRepository::next_update_id(&conn).unwrap());
+        UpdateRepository::confirm(13, &conn).unwrap();
+        assert_eq!(Some(13), UpdateRepository::next_update_id(&conn).unwrap());
+
+        assert!(UpdateRepository::is_processed(12, &conn).unwrap());
+        assert!(!UpdateRepository::is_processed(11, &conn).unwrap());
+        assert_eq!(1, UpdateRepository::prune(12, &conn).unwrap());
+        assert!(!UpdateRepository::is_processed(10, &conn).unwrap());
+        assert!(UpdateRepository::claim(10, &conn).unwrap());
+        Ok(())
+    });
+}
@@ -11,6 +11,11 @@ Feature: Bot feature
 /help
 /history
 /add
+/share
+/join
+/leave
+/settle
+/chart
 
 If you encounter any issues feel free to open an issue.
 Or you can also send feedback via /feedback command."
@@ -1,14 +1,15 @@
 extern crate diesel_migrations;
 
-mod bot {
-    mod api_integration_test;
-}
-
 mod db {
+    mod database;
+
     mod model {
         mod dialog;
         mod history;
-        mod test_helper;
+        mod ledger;
+        mod split;
+        pub(crate) mod test_helper;
+        mod update;
         mod user;
     }
 }